@@ -30,17 +30,44 @@ window.addEventListener('keydown', e => {
 
 // === Simulation ===
 await init(); // load WASM module
-const world = new WASMWorld(volume_size, 360.8, 0.0001, 1);  // create world
+const world = new WASMWorld(volume_size, "water", 0.1, 1);  // create world
 world.add_particle("e-", 0, 0, 0, 5, 0, 0);  // add particle
 
 while (world.has_alive_particles()) {
     world.step();
 }
-const points = world.get_particle_position_history().map(([x, y, z]) => new THREE.Vector3(x, y, z));
-const geometry = new THREE.BufferGeometry().setFromPoints(points);
-const material = new THREE.LineBasicMaterial({ color: 0xff0000 });
-const line = new THREE.Line(geometry, material);
-visualizer.scene.add(line);
+// === Tracks ===
+// One line per track, colored by species
+const species_colors = {
+    Electron: 0xff0000,
+    Positron: 0x0000ff,
+    Muon: 0x00ff00,
+    AntiMuon: 0x00ffaa,
+    Gamma: 0xffff00,
+    Neutron: 0xaaaaaa,
+    PionPlus: 0xff00ff,
+    PionMinus: 0xaa00ff,
+    Proton: 0xff8800,
+    OpticalPhoton: 0x88ccff,
+};
+const tracks = world.get_trajectories().map(trajectory => {
+    const geometry = new THREE.BufferGeometry();
+    const material = new THREE.LineBasicMaterial({ color: species_colors[trajectory.species] });
+    visualizer.scene.add(new THREE.Line(geometry, material));
+    return { points: trajectory.points, geometry: geometry };
+});
+
+// === Replay ===
+// The tracks are drawn up to the replay time, which advances at a fixed rate (ns of simulation per second)
+const replay_speed = 1.0;
+const replay_start = performance.now();
+function update_replay() {
+    const replay_time = (performance.now() - replay_start) / 1000 * replay_speed;
+    for (const track of tracks) {
+        const points = track.points.filter(point => point.time <= replay_time).map(({ position: [x, y, z] }) => new THREE.Vector3(x, y, z));
+        track.geometry.setFromPoints(points);
+    }
+}
 
 // === Animation loop ===
 function animate() {
@@ -48,6 +75,7 @@ function animate() {
 
     requestAnimationFrame(animate);
 
+    update_replay();
     visualizer.update_controls();
     visualizer.render();
 
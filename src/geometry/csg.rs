@@ -0,0 +1,258 @@
+use std::sync::Arc;
+
+use crate::geometry::shape::{Shape, TOLERANCE};
+use crate::geometry::transform::Transform;
+use crate::utils::vec3::Vec3;
+
+// Maximum number of component surfaces crossed by a ray before the boundary of a composed shape is found
+const MAX_CROSSINGS: usize = 1000;
+
+// Distance from a point on a surface at which the sides of the surface are probed to pick a normal (mm)
+const PROBE: f64 = 1e-6;
+
+// Shape moved and rotated into the frame of its parent
+#[derive(Debug, Clone)]
+pub struct Transformed {
+    pub shape: Arc<dyn Shape>,
+    pub transform: Transform,
+}
+
+impl Transformed {
+    pub fn new(shape: impl Shape + 'static, transform: Transform) -> Self {
+        Transformed { shape: Arc::new(shape), transform }
+    }
+}
+
+impl Shape for Transformed {
+    fn contains(&self, r: Vec3) -> bool {
+        self.shape.contains(self.transform.to_local(r))
+    }
+
+    fn distance_to_in(&self, r: Vec3, dir: Vec3) -> f64 {
+        self.shape.distance_to_in(self.transform.to_local(r), self.transform.direction_to_local(dir))
+    }
+
+    fn distance_to_out(&self, r: Vec3, dir: Vec3) -> f64 {
+        self.shape.distance_to_out(self.transform.to_local(r), self.transform.direction_to_local(dir))
+    }
+
+    fn normal(&self, r: Vec3) -> Vec3 {
+        self.transform.direction_to_parent(self.shape.normal(self.transform.to_local(r)))
+    }
+
+    fn bounding_box(&self) -> (Vec3, Vec3) {
+        // Box around the transformed corners of the box of the shape
+        let (low, high) = self.shape.bounding_box();
+        let corners = (0..8).map(|i| self.transform.to_parent(Vec3(
+            if i & 1 == 0 { low.0 } else { high.0 },
+            if i & 2 == 0 { low.1 } else { high.1 },
+            if i & 4 == 0 { low.2 } else { high.2 },
+        )));
+        corners.fold((Vec3(f64::INFINITY, f64::INFINITY, f64::INFINITY), Vec3(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY)),
+            |(low, high), c| (Vec3(low.0.min(c.0), low.1.min(c.1), low.2.min(c.2)), Vec3(high.0.max(c.0), high.1.max(c.1), high.2.max(c.2))))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operation {
+    Union,         // points in either shape
+    Intersection,  // points in both shapes
+    Subtraction,   // points in the first shape but not in the second
+}
+
+// Constructive solid geometry: combination of two shapes, in the same frame. The second shape is usually
+// `Transformed` to place it relative to the first.
+#[derive(Debug, Clone)]
+pub struct Boolean {
+    pub operation: Operation,
+    pub first: Arc<dyn Shape>,
+    pub second: Arc<dyn Shape>,
+}
+
+impl Boolean {
+    pub fn new(operation: Operation, first: impl Shape + 'static, second: impl Shape + 'static) -> Self {
+        Boolean { operation, first: Arc::new(first), second: Arc::new(second) }
+    }
+
+    pub fn union(first: impl Shape + 'static, second: impl Shape + 'static) -> Self {
+        Boolean::new(Operation::Union, first, second)
+    }
+
+    pub fn intersection(first: impl Shape + 'static, second: impl Shape + 'static) -> Self {
+        Boolean::new(Operation::Intersection, first, second)
+    }
+
+    pub fn subtraction(first: impl Shape + 'static, second: impl Shape + 'static) -> Self {
+        Boolean::new(Operation::Subtraction, first, second)
+    }
+
+    // Distance along a ray to the next point where it enters (inside = false) or leaves (inside = true) the composed
+    // shape, found by stepping from one component surface to the next
+    fn march(&self, r: Vec3, dir: Vec3, inside: bool) -> f64 {
+        let mut t = 0.0;
+        for _ in 0..MAX_CROSSINGS {
+            if self.contains(r + dir * (t + TOLERANCE)) != inside {
+                return t;
+            }
+            let step = f64::min(next_surface(&*self.first, r + dir * t, dir), next_surface(&*self.second, r + dir * t, dir));
+            if step.is_infinite() {
+                return if inside { t } else { f64::INFINITY };
+            }
+            t += step.max(TOLERANCE);
+        }
+        t
+    }
+}
+
+// Distance along a ray to the next surface of a shape, leaving or entering it
+fn next_surface(shape: &dyn Shape, r: Vec3, dir: Vec3) -> f64 {
+    if shape.contains(r + dir * TOLERANCE) {
+        shape.distance_to_out(r, dir)
+    } else {
+        shape.distance_to_in(r, dir)
+    }
+}
+
+// Whether a point lies on the surface of a shape
+fn on_surface(shape: &dyn Shape, r: Vec3) -> bool {
+    let n = shape.normal(r);
+    shape.contains(r - n * PROBE) && !shape.contains(r + n * PROBE)
+}
+
+impl Shape for Boolean {
+    fn contains(&self, r: Vec3) -> bool {
+        match self.operation {
+            Operation::Union        => self.first.contains(r) || self.second.contains(r),
+            Operation::Intersection => self.first.contains(r) && self.second.contains(r),
+            Operation::Subtraction  => self.first.contains(r) && !self.second.contains(r),
+        }
+    }
+
+    fn distance_to_in(&self, r: Vec3, dir: Vec3) -> f64 {
+        self.march(r, dir, false)
+    }
+
+    fn distance_to_out(&self, r: Vec3, dir: Vec3) -> f64 {
+        self.march(r, dir, true)
+    }
+
+    fn normal(&self, r: Vec3) -> Vec3 {
+        // Normal of the first shape if the point is on the part of its surface bounding the composed shape, otherwise
+        // normal of the second shape
+        let n = self.first.normal(r);
+        let first = on_surface(&*self.first, r) && match self.operation {
+            Operation::Union        => !self.second.contains(r + n * PROBE),
+            Operation::Intersection => self.second.contains(r - n * PROBE),
+            Operation::Subtraction  => !self.second.contains(r - n * PROBE),
+        };
+        match (first, self.operation) {
+            (true, _) => n,
+            (false, Operation::Subtraction) => -self.second.normal(r),
+            (false, _) => self.second.normal(r),
+        }
+    }
+
+    fn bounding_box(&self) -> (Vec3, Vec3) {
+        let (low, high) = self.first.bounding_box();
+        let (low2, high2) = self.second.bounding_box();
+        match self.operation {
+            Operation::Union => (Vec3(low.0.min(low2.0), low.1.min(low2.1), low.2.min(low2.2)),
+                                 Vec3(high.0.max(high2.0), high.1.max(high2.1), high.2.max(high2.2))),
+            Operation::Intersection => (Vec3(low.0.max(low2.0), low.1.max(low2.1), low.2.max(low2.2)),
+                                        Vec3(high.0.min(high2.0), high.1.min(high2.1), high.2.min(high2.2))),
+            Operation::Subtraction => (low, high),
+        }
+    }
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use crate::assert_vec3_eq;
+    use crate::geometry::shape::{Cuboid, Cylinder, Sphere};
+    use crate::geometry::transform::Rotation;
+    use std::f64::consts::PI;
+
+    const ORIGIN: Vec3 = Vec3(0.0, 0.0, 0.0);
+    const X: Vec3 = Vec3(1.0, 0.0, 0.0);
+    const Z: Vec3 = Vec3(0.0, 0.0, 1.0);
+
+    #[test]
+    fn test_csg_transformed() {
+        // Box along x, rotated to lie along y, and moved up
+        let bar = Transformed::new(Cuboid::new(ORIGIN, Vec3(10.0, 1.0, 1.0)), Transform::new(Rotation::about_z(PI / 2.0), Vec3(0.0, 0.0, 5.0)));
+        assert!(bar.contains(Vec3(0.5, 9.0, 5.5)));
+        assert!(!bar.contains(Vec3(9.0, 0.5, 5.5)));
+        assert!(!bar.contains(Vec3(0.0, 0.0, 0.0)));
+        assert_relative_eq!(bar.distance_to_in(ORIGIN, Z), 4.0, max_relative = 1e-12);
+        assert_relative_eq!(bar.distance_to_out(Vec3(0.0, 0.0, 5.0), Vec3(0.0, 1.0, 0.0)), 10.0, max_relative = 1e-12);
+        assert_vec3_eq!(bar.normal(Vec3(0.0, 10.0, 5.0)), Vec3(0.0, 1.0, 0.0), 1e-9);
+        assert_vec3_eq!(bar.normal(Vec3(-1.0, 2.0, 5.0)), Vec3(-1.0, 0.0, 0.0), 1e-9);
+        let (low, high) = bar.bounding_box();
+        assert_vec3_eq!(low, Vec3(-1.0, -10.0, 4.0), 1e-9);
+        assert_vec3_eq!(high, Vec3(1.0, 10.0, 6.0), 1e-9);
+    }
+
+    #[test]
+    fn test_csg_union() {
+        // Two overlapping spheres along x
+        let union = Boolean::union(Sphere::new(ORIGIN, 2.0), Sphere::new(Vec3(3.0, 0.0, 0.0), 2.0));
+        assert!(union.contains(Vec3(-1.5, 0.0, 0.0)));
+        assert!(union.contains(Vec3(4.5, 0.0, 0.0)));
+        assert!(!union.contains(Vec3(1.5, 1.9, 0.0)));
+        // Through both spheres without stopping at the inner surfaces
+        assert_relative_eq!(union.distance_to_in(Vec3(-10.0, 0.0, 0.0), X), 8.0);
+        assert_relative_eq!(union.distance_to_out(ORIGIN, X), 5.0);
+        assert_relative_eq!(union.distance_to_out(ORIGIN, -X), 2.0);
+        assert_eq!(union.distance_to_in(Vec3(-10.0, 0.0, 0.0), -X), f64::INFINITY);
+        assert_eq!(union.distance_to_in(Vec3(-2.0, 0.0, 0.0), -X), f64::INFINITY);
+        // Between the spheres, above their intersection
+        assert_relative_eq!(union.distance_to_in(Vec3(1.5, 5.0, 0.0), Vec3(0.0, -1.0, 0.0)), 5.0 - 1.75f64.sqrt(), max_relative = 1e-12);
+        assert_vec3_eq!(union.normal(Vec3(-2.0, 0.0, 0.0)), -X);
+        assert_vec3_eq!(union.normal(Vec3(5.0, 0.0, 0.0)), X);
+        assert_vec3_eq!(union.bounding_box(), (Vec3(-2.0, -2.0, -2.0), Vec3(5.0, 2.0, 2.0)));
+    }
+
+    #[test]
+    fn test_csg_intersection() {
+        // Lens: intersection of two spheres
+        let lens = Boolean::intersection(Sphere::new(ORIGIN, 2.0), Sphere::new(Vec3(3.0, 0.0, 0.0), 2.0));
+        assert!(lens.contains(Vec3(1.5, 0.0, 0.0)));
+        assert!(!lens.contains(Vec3(0.5, 0.0, 0.0)));
+        assert_relative_eq!(lens.distance_to_in(Vec3(-10.0, 0.0, 0.0), X), 11.0);
+        assert_relative_eq!(lens.distance_to_out(Vec3(1.5, 0.0, 0.0), X), 0.5);
+        assert_relative_eq!(lens.distance_to_out(Vec3(1.5, 0.0, 0.0), Vec3(0.0, 0.0, 1.0)), 1.75f64.sqrt(), max_relative = 1e-12);
+        assert_eq!(lens.distance_to_in(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 1.0)), f64::INFINITY);
+        assert_vec3_eq!(lens.normal(Vec3(2.0, 0.0, 0.0)), X);
+        assert_vec3_eq!(lens.normal(Vec3(1.0, 0.0, 0.0)), -X);
+        let (low, high) = lens.bounding_box();
+        assert_vec3_eq!(low, Vec3(1.0, -2.0, -2.0));
+        assert_vec3_eq!(high, Vec3(2.0, 2.0, 2.0));
+    }
+
+    #[test]
+    fn test_csg_subtraction() {
+        // Box with a tilted cylindrical hole drilled through it
+        let hole = Transformed::new(Cylinder::new(ORIGIN, Z, 1.0, 20.0), Transform::new(Rotation::about_y(PI / 2.0), Vec3(0.0, 0.0, 2.0)));
+        let block = Boolean::subtraction(Cuboid::new(ORIGIN, Vec3(5.0, 5.0, 5.0)), hole);
+        assert!(block.contains(Vec3(0.0, 0.0, 0.0)));
+        assert!(!block.contains(Vec3(3.0, 0.0, 2.5)));
+        assert!(!block.contains(Vec3(6.0, 0.0, 0.0)));
+        // Up through the hole, and along it
+        assert_relative_eq!(block.distance_to_out(ORIGIN, Z), 1.0, max_relative = 1e-12);
+        assert_relative_eq!(block.distance_to_in(Vec3(0.0, 0.0, 2.0), Z), 1.0, max_relative = 1e-12);
+        assert_relative_eq!(block.distance_to_in(Vec3(-10.0, 0.0, 0.0), X), 5.0);
+        assert_eq!(block.distance_to_in(Vec3(-10.0, 0.0, 2.0), X), f64::INFINITY);
+        assert_vec3_eq!(block.normal(Vec3(0.0, 0.0, 1.0)), Z, 1e-9);
+        assert_vec3_eq!(block.normal(Vec3(2.0, 0.0, 3.0)), -Z, 1e-9);
+        assert_vec3_eq!(block.normal(Vec3(-5.0, 0.0, -2.0)), -X, 1e-9);
+        // Composed shapes can be composed again
+        let hollow = Boolean::subtraction(block, Sphere::new(Vec3(0.0, 0.0, -3.0), 1.0));
+        assert_relative_eq!(hollow.distance_to_in(Vec3(0.0, 0.0, -3.0), -Z), 1.0, max_relative = 1e-12);
+        assert_relative_eq!(hollow.distance_to_out(Vec3(0.0, 0.0, -4.5), Z), 0.5, max_relative = 1e-12);
+        assert_relative_eq!(hollow.distance_to_out(Vec3(0.0, 0.0, -4.5), -Z), 0.5, max_relative = 1e-12);
+    }
+}
@@ -0,0 +1,183 @@
+use rand::Rng;
+use serde::Serialize;
+
+use crate::utils::vec3::Vec3;
+use crate::geometry::material::POLYSTYRENE;
+use crate::utils::constants::C;
+
+// Direction of a fibre
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl Axis {
+    // Split a point into its coordinate along the axis, and its two transverse coordinates
+    pub fn split(self, r: Vec3) -> (f64, f64, f64) {
+        match self {
+            Axis::X => (r.0, r.1, r.2),
+            Axis::Y => (r.1, r.0, r.2),
+            Axis::Z => (r.2, r.0, r.1),
+        }
+    }
+}
+
+// Wavelength-shifting fibre, running through the whole volume and read out at its positive end
+#[derive(Debug, Clone, Serialize)]
+pub struct Fibre {
+    pub axis: Axis,
+    pub u: f64,          // first transverse coordinate (y for X fibres, x for Y and Z fibres) (mm)
+    pub v: f64,          // second transverse coordinate (z for X and Y fibres, y for Z fibres) (mm)
+    pub hits: Vec<f64>,  // arrival times of the photons at the readout (ns)
+}
+
+// Regular lattice of fibres along x, y and z filling the cubic volume (LiquidO)
+#[derive(Clone)]
+pub struct FibreLattice {
+    pub size: f64,                // edge length of the volume (mm)
+    pub pitch: f64,               // distance between neighbouring fibres of the same direction (mm)
+    pub capture_radius: f64,      // photons passing closer than this to a fibre are captured (mm)
+    pub attenuation_length: f64,  // attenuation length of the light along the fibre (mm)
+    pub n: usize,                 // number of fibres per row
+    pub fibres: Vec<Fibre>,
+}
+
+impl FibreLattice {
+    // Each direction is shifted by a different fraction of the pitch, so that fibres never intersect
+    pub fn new(size: f64, pitch: f64, capture_radius: f64, attenuation_length: f64) -> Self {
+        let n = (size / pitch) as usize;
+        let mut fibres = vec![];
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            for i in 0..n {
+                for j in 0..n {
+                    let (u, v) = (Self::coordinate(size, pitch, axis, i), Self::coordinate(size, pitch, axis, j));
+                    fibres.push(Fibre { axis, u, v, hits: vec![] });
+                }
+            }
+        }
+        FibreLattice { size, pitch, capture_radius, attenuation_length, n, fibres }
+    }
+
+    // Transverse coordinate of the fibres in row i
+    fn coordinate(size: f64, pitch: f64, axis: Axis, i: usize) -> f64 {
+        let shift = match axis {
+            Axis::X => 0.25,
+            Axis::Y => 0.5,
+            Axis::Z => 0.75,
+        };
+        -size / 2.0 + (i as f64 + shift) * pitch
+    }
+
+    // Index of a fibre in the list
+    pub fn index(&self, axis: Axis, i: usize, j: usize) -> usize {
+        let a = match axis {
+            Axis::X => 0,
+            Axis::Y => 1,
+            Axis::Z => 2,
+        };
+        (a * self.n + i) * self.n + j
+    }
+
+    // Number of photons detected by each fibre
+    pub fn hit_counts(&self) -> Vec<usize> {
+        self.fibres.iter().map(|fibre| fibre.hits.len()).collect()
+    }
+
+    // Capture an optical photon travelling from r0 (at time t0) to r1 (at time t1) by the first fibre it passes
+    // close enough to. The light then travels to the end of the fibre, where it is detected unless it is
+    // attenuated on the way. Returns whether the photon was captured.
+    pub fn capture(&mut self, rng: &mut impl Rng, r0: Vec3, r1: Vec3, t0: f64, t1: f64) -> bool {
+        let mut first: Option<(usize, f64)> = None;  // fibre index and fraction of the segment
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            let (_, u0, v0) = axis.split(r0);
+            let (_, u1, v1) = axis.split(r1);
+            let (du, dv) = (u1 - u0, v1 - v0);
+            // Only look at the rows close to the segment
+            let rows = |a: f64, b: f64| {
+                let lo = (a.min(b) - self.capture_radius + self.size / 2.0) / self.pitch - 1.0;
+                let hi = (a.max(b) + self.capture_radius + self.size / 2.0) / self.pitch;
+                (lo.max(0.0) as usize)..(hi.max(0.0) as usize + 1).min(self.n)
+            };
+            for i in rows(u0, u1) {
+                for j in rows(v0, v1) {
+                    let index = self.index(axis, i, j);
+                    let (u, v) = (self.fibres[index].u, self.fibres[index].v);
+                    // Closest point of the (projected) segment to the fibre
+                    let len2 = du*du + dv*dv;
+                    let f = if len2 > 0.0 { (((u - u0)*du + (v - v0)*dv) / len2).clamp(0.0, 1.0) } else { 0.0 };
+                    let d2 = (u0 + f*du - u).powi(2) + (v0 + f*dv - v).powi(2);
+                    if d2 < self.capture_radius.powi(2) && first.is_none_or(|(_, f_first)| f < f_first) {
+                        first = Some((index, f));
+                    }
+                }
+            }
+        }
+
+        let Some((index, f)) = first else {
+            return false;
+        };
+        let fibre = &mut self.fibres[index];
+        let (w, _, _) = fibre.axis.split(r0 + (r1 - r0) * f);
+        let distance = self.size / 2.0 - w;
+        if rng.random::<f64>() < f64::exp(-distance / self.attenuation_length) {
+            fibre.hits.push(t0 + (t1 - t0) * f + distance / (C / POLYSTYRENE.n));
+        }
+        true
+    }
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_fibre_lattice_creation() {
+        let lattice = FibreLattice::new(100.0, 10.0, 0.5, 3500.0);
+        assert_eq!(lattice.n, 10);
+        assert_eq!(lattice.fibres.len(), 300);
+        let fibre = &lattice.fibres[lattice.index(Axis::Y, 2, 7)];
+        assert_eq!(fibre.axis, Axis::Y);
+        assert_relative_eq!(fibre.u, -25.0);
+        assert_relative_eq!(fibre.v, 25.0);
+        // Fibres stay inside the volume
+        assert!(lattice.fibres.iter().all(|fibre| fibre.u.abs() < 50.0 && fibre.v.abs() < 50.0));
+        assert!(lattice.hit_counts().iter().all(|&count| count == 0));
+    }
+
+    #[test]
+    fn test_fibre_lattice_capture() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut lattice = FibreLattice::new(100.0, 10.0, 0.5, 1e12);
+        // Z fibre at (x, y) = (-2.5, -2.5): a photon along x passing 0.3mm away is captured
+        assert!(lattice.capture(&mut rng, Vec3(-10.0, -2.8, 20.0), Vec3(0.0, -2.8, 20.0), 1.0, 2.0));
+        let counts = lattice.hit_counts();
+        assert_eq!(counts.iter().sum::<usize>(), 1);
+        let fibre = &lattice.fibres[lattice.index(Axis::Z, 4, 4)];
+        assert_eq!(counts[lattice.index(Axis::Z, 4, 4)], 1);
+        assert_relative_eq!(fibre.u, -2.5);
+        assert_relative_eq!(fibre.v, -2.5);
+        // Captured 3/4 of the way, 30mm away from the readout
+        assert_relative_eq!(fibre.hits[0], 1.75 + 30.0 / (C / POLYSTYRENE.n), max_relative = 1e-9);
+
+        // Photon between the fibres
+        assert!(!lattice.capture(&mut rng, Vec3(-10.0, -2.0, 19.0), Vec3(-10.0, -2.0, 21.0), 0.0, 1.0));
+
+        // Photon crossing several fibres is captured by the first one
+        assert!(lattice.capture(&mut rng, Vec3(-50.0, -2.5, 1.0), Vec3(50.0, -2.5, 1.0), 0.0, 1.0));
+        assert_eq!(lattice.fibres[lattice.index(Axis::Z, 0, 4)].hits.len(), 1);
+
+        // Light attenuated along the fibre
+        let mut attenuating = FibreLattice::new(100.0, 10.0, 0.5, 50.0);
+        for _ in 0..10000 {
+            attenuating.capture(&mut rng, Vec3(-10.0, -2.8, -50.0), Vec3(0.0, -2.8, -50.0), 0.0, 1.0);
+        }
+        let detected = attenuating.hit_counts().iter().sum::<usize>();
+        assert_relative_eq!(detected as f64 / 10000.0, f64::exp(-2.0), max_relative = 0.05);
+    }
+}
@@ -0,0 +1,384 @@
+use std::path::Path;
+
+use crate::utils::constants::KAPPA;
+use crate::utils::vec3::Vec3;
+
+// Force of an electric field of 1 V/m on a unit charge (MeV/mm)
+pub(crate) const VOLT_PER_METRE: f64 = 1e-9;
+
+// Errors allowed on each Runge-Kutta step: on the position (mm), and on the momentum (relative)
+const POSITION_TOLERANCE: f64 = 1e-6;
+const MOMENTUM_TOLERANCE: f64 = 1e-9;
+
+// Shortest Runge-Kutta step (mm), accepted whatever its error so that tracks always make progress
+const MIN_STEP: f64 = 1e-6;
+
+// Magic bytes at the start of binary field maps
+const MAGIC: &[u8; 4] = b"FMAP";
+
+// Electromagnetic field in which the particles move
+#[derive(Debug, Clone, PartialEq)]
+pub enum Field {
+    Uniform(Vec3),  // uniform magnetic field (T), followed with exact helix steps
+    Map(FieldMap),  // magnetic and electric field maps, followed with adaptive Runge-Kutta steps
+}
+
+impl Default for Field {
+    fn default() -> Self {
+        Field::Uniform(Vec3(0.0, 0.0, 0.0))
+    }
+}
+
+impl Field {
+    // Magnetic field at a point (T)
+    pub fn magnetic(&self, r: Vec3) -> Vec3 {
+        match self {
+            Field::Uniform(b) => *b,
+            Field::Map(map)   => map.interpolate(&map.magnetic, r),
+        }
+    }
+
+    // Electric field at a point (V/m)
+    pub fn electric(&self, r: Vec3) -> Vec3 {
+        match self {
+            Field::Uniform(_) => Vec3(0.0, 0.0, 0.0),
+            Field::Map(map)   => map.interpolate(&map.electric, r),
+        }
+    }
+
+    // Position and momentum of a particle of mass m (MeV) and charge q (e) after a path length s (mm), starting at r
+    // with momentum p (MeV)
+    pub fn track(&self, r: Vec3, p: Vec3, m: f64, q: f64, s: f64) -> (Vec3, Vec3) {
+        match self {
+            _ if q == 0.0     => (r + p.norm() * s, p),
+            Field::Uniform(b) => helix(r, p, q, *b, s),
+            Field::Map(map)   => map.track(r, p, m, q, s),
+        }
+    }
+}
+
+// Exact step along a helix around a uniform magnetic field b (T). The momentum turns around the field at k rad/mm,
+// as dp/ds = KAPPA q p/|p| x B.
+fn helix(r: Vec3, p: Vec3, q: f64, b: Vec3, s: f64) -> (Vec3, Vec3) {
+    let k = KAPPA * q * b.mag() / p.mag();
+    if (k * s).abs() < 1e-9 {
+        return (r + p.norm() * s, p);
+    }
+    let axis = b.norm();
+    let parallel = axis * p.dot(axis);
+    let perpendicular = p - parallel;
+    let side = axis.cross(perpendicular);
+    let (sin, cos) = (k * s).sin_cos();
+    let r = r + (parallel * s + perpendicular * (sin / k) + side * ((cos - 1.0) / k)) / p.mag();
+    (r, parallel + perpendicular * cos - side * sin)
+}
+
+// Magnetic and electric fields sampled on a regular 3D grid, and interpolated trilinearly between the grid points.
+// The fields vanish outside the grid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldMap {
+    pub origin: Vec3,         // position of the first grid point (mm)
+    pub spacing: Vec3,        // distance between grid points along x, y and z (mm)
+    pub size: [usize; 3],     // number of grid points along x, y and z
+    pub magnetic: Vec<Vec3>,  // magnetic field at the grid points (T), with z varying fastest, then y, then x
+    pub electric: Vec<Vec3>,  // electric field at the grid points (V/m)
+}
+
+impl FieldMap {
+    // Parse a field map from comma (or whitespace) separated lines x, y, z (mm), Bx, By, Bz (T) and optionally
+    // Ex, Ey, Ez (V/m), in any order. The points must cover a regular grid. Lines which are not only numbers
+    // (headers, comments) are skipped.
+    pub fn parse_csv(text: &str) -> Result<Self, String> {
+        let mut rows = vec![];
+        for line in text.lines() {
+            let Ok(values) = line.split(|c: char| c == ',' || c.is_whitespace()).filter(|value| !value.is_empty())
+                .map(str::parse::<f64>).collect::<Result<Vec<f64>, _>>() else {
+                continue;
+            };
+            match values.len() {
+                0 => continue,
+                6 | 9 => rows.push(values),
+                n => return Err(format!("expected 6 or 9 columns, found {n}: {}", line.trim())),
+            }
+        }
+
+        // Grid coordinates along each axis
+        let axis = |c: usize| -> Result<(f64, f64, usize), String> {
+            let mut values = rows.iter().map(|row| row[c]).collect::<Vec<f64>>();
+            values.sort_by(f64::total_cmp);
+            values.dedup();
+            let spacing = if values.len() > 1 { values[1] - values[0] } else { 1.0 };
+            if values.windows(2).any(|pair| ((pair[1] - pair[0]) - spacing).abs() > 1e-6 * spacing) {
+                return Err("the points are not on a regular grid".to_string());
+            }
+            Ok((values[0], spacing, values.len()))
+        };
+        if rows.is_empty() {
+            return Err("no field map points".to_string());
+        }
+        let ((x0, dx, nx), (y0, dy, ny), (z0, dz, nz)) = (axis(0)?, axis(1)?, axis(2)?);
+        if rows.len() != nx * ny * nz {
+            return Err(format!("{} points do not fill a {nx} x {ny} x {nz} grid", rows.len()));
+        }
+
+        let mut map = FieldMap {
+            origin: Vec3(x0, y0, z0),
+            spacing: Vec3(dx, dy, dz),
+            size: [nx, ny, nz],
+            magnetic: vec![Vec3(0.0, 0.0, 0.0); rows.len()],
+            electric: vec![Vec3(0.0, 0.0, 0.0); rows.len()],
+        };
+        let mut filled = vec![false; rows.len()];
+        for row in rows {
+            let index = |c: usize, origin: f64, spacing: f64| ((row[c] - origin) / spacing).round() as usize;
+            let i = map.index(index(0, x0, dx), index(1, y0, dy), index(2, z0, dz));
+            if filled[i] {
+                return Err(format!("duplicate point ({}, {}, {})", row[0], row[1], row[2]));
+            }
+            filled[i] = true;
+            map.magnetic[i] = Vec3(row[3], row[4], row[5]);
+            if row.len() == 9 {
+                map.electric[i] = Vec3(row[6], row[7], row[8]);
+            }
+        }
+        Ok(map)
+    }
+
+    // Parse a binary field map: the bytes "FMAP", the number of points along x, y and z (u32), the origin and the
+    // spacing (f64), then Bx, By, Bz, Ex, Ey, Ez (f64) at each grid point, with z varying fastest. All the numbers are
+    // little-endian.
+    pub fn from_binary(bytes: &[u8]) -> Result<Self, String> {
+        let header = 4 + 3 * 4 + 6 * 8;
+        if bytes.len() < header || &bytes[..4] != MAGIC {
+            return Err("not a binary field map".to_string());
+        }
+        let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap()) as usize;
+        let f64_at = |i: usize| f64::from_le_bytes(bytes[i..i + 8].try_into().unwrap());
+        let vec3_at = |i: usize| Vec3(f64_at(i), f64_at(i + 8), f64_at(i + 16));
+
+        // The sizes come from the file: the number of bytes they need is checked against overflows
+        let size = [u32_at(4), u32_at(8), u32_at(12)];
+        let points = size.iter().try_fold(1, |points: usize, &n| points.checked_mul(n));
+        let length = points.and_then(|points| points.checked_mul(6 * 8)?.checked_add(header));
+        let points = match (points, length) {
+            (Some(points), Some(length)) if points > 0 && length == bytes.len() => points,
+            _ => return Err(format!("binary field map of {} bytes does not match its {} x {} x {} grid", bytes.len(), size[0], size[1], size[2])),
+        };
+        let (origin, spacing) = (vec3_at(16), vec3_at(40));
+        if [spacing.0, spacing.1, spacing.2].iter().any(|d| !d.is_finite() || *d <= 0.0) {
+            return Err(format!("invalid grid spacing ({}, {}, {})", spacing.0, spacing.1, spacing.2));
+        }
+        let magnetic = (0..points).map(|i| vec3_at(header + i * 48)).collect();
+        let electric = (0..points).map(|i| vec3_at(header + i * 48 + 24)).collect();
+        Ok(FieldMap { origin, spacing, size, magnetic, electric })
+    }
+
+    // Binary form of the field map, read by `from_binary` (e.g. to convert a large CSV map once)
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        for n in self.size {
+            bytes.extend((n as u32).to_le_bytes());
+        }
+        let vectors = [self.origin, self.spacing].into_iter()
+            .chain(self.magnetic.iter().zip(&self.electric).flat_map(|(b, e)| [*b, *e]));
+        for v in vectors {
+            for x in [v.0, v.1, v.2] {
+                bytes.extend(x.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    // Load a field map from a file: CSV (.csv or .txt), or binary otherwise
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let error = |e: String| format!("{}: {}", path.display(), e);
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("csv" | "txt") => FieldMap::parse_csv(&std::fs::read_to_string(path).map_err(|e| error(e.to_string()))?).map_err(error),
+            _ => FieldMap::from_binary(&std::fs::read(path).map_err(|e| error(e.to_string()))?).map_err(error),
+        }
+    }
+
+    fn index(&self, i: usize, j: usize, k: usize) -> usize {
+        (i * self.size[1] + j) * self.size[2] + k
+    }
+
+    // Trilinear interpolation of the values at the grid points (zero outside the grid)
+    fn interpolate(&self, values: &[Vec3], r: Vec3) -> Vec3 {
+        let u = r - self.origin;
+        let mut cell = [(0, 0.0); 3];  // index of the lower grid point, and fractional position in the cell
+        for (c, (x, spacing)) in [(u.0, self.spacing.0), (u.1, self.spacing.1), (u.2, self.spacing.2)].into_iter().enumerate() {
+            let last = (self.size[c] - 1) as f64;
+            let x = x / spacing;
+            if !(0.0..=last).contains(&x) {
+                return Vec3(0.0, 0.0, 0.0);
+            }
+            let i = x.floor().min((last - 1.0).max(0.0));
+            cell[c] = (i as usize, x - i);
+        }
+        let [(i, fx), (j, fy), (k, fz)] = cell;
+        let mut value = Vec3(0.0, 0.0, 0.0);
+        for (di, wx) in [(0, 1.0 - fx), (1, fx)] {
+            for (dj, wy) in [(0, 1.0 - fy), (1, fy)] {
+                for (dk, wz) in [(0, 1.0 - fz), (1, fz)] {
+                    let w = wx * wy * wz;
+                    if w > 0.0 {
+                        value += values[self.index(i + di, j + dj, k + dk)] * w;
+                    }
+                }
+            }
+        }
+        value
+    }
+
+    // Integrate the equation of motion over a path length s (mm) with adaptive Runge-Kutta-Fehlberg 4(5) steps:
+    // dr/ds = p/|p| and dp/ds = q (KAPPA p/|p| x B + E / beta), E in MeV/mm
+    fn track(&self, r: Vec3, p: Vec3, m: f64, q: f64, s: f64) -> (Vec3, Vec3) {
+        let derivative = |(r, p): (Vec3, Vec3)| {
+            let (dir, beta) = (p.norm(), p.mag() / (p.mag().powi(2) + m*m).sqrt());
+            let e = self.interpolate(&self.electric, r) * VOLT_PER_METRE;
+            (dir, (dir.cross(self.interpolate(&self.magnetic, r)) * KAPPA + e / beta) * q)
+        };
+        let (mut y, mut done, mut h) = ((r, p), 0.0, s);
+        while s - done > 1e-12 {
+            h = h.min(s - done);
+            let (next, error) = rkf45(&derivative, y, h);
+            let error = f64::max(error.0.mag() / POSITION_TOLERANCE, error.1.mag() / (MOMENTUM_TOLERANCE * y.1.mag()));
+            if error <= 1.0 || h <= MIN_STEP {
+                (y, done) = (next, done + h);
+            }
+            h = (h * (0.9 * error.powf(-0.2)).clamp(0.2, 5.0)).max(MIN_STEP);
+        }
+        y
+    }
+}
+
+type State = (Vec3, Vec3);
+
+// State y + h sum(c_i k_i)
+fn advance(y: State, h: f64, terms: &[(f64, State)]) -> State {
+    terms.iter().fold(y, |(r, p), &(c, (dr, dp))| (r + dr * (h * c), p + dp * (h * c)))
+}
+
+// One Runge-Kutta-Fehlberg step of length h: the fifth order solution, and its difference with the fourth order one
+fn rkf45(f: &impl Fn(State) -> State, y: State, h: f64) -> (State, State) {
+    let k1 = f(y);
+    let k2 = f(advance(y, h, &[(1.0/4.0, k1)]));
+    let k3 = f(advance(y, h, &[(3.0/32.0, k1), (9.0/32.0, k2)]));
+    let k4 = f(advance(y, h, &[(1932.0/2197.0, k1), (-7200.0/2197.0, k2), (7296.0/2197.0, k3)]));
+    let k5 = f(advance(y, h, &[(439.0/216.0, k1), (-8.0, k2), (3680.0/513.0, k3), (-845.0/4104.0, k4)]));
+    let k6 = f(advance(y, h, &[(-8.0/27.0, k1), (2.0, k2), (-3544.0/2565.0, k3), (1859.0/4104.0, k4), (-11.0/40.0, k5)]));
+    let fifth = advance(y, h, &[(16.0/135.0, k1), (6656.0/12825.0, k3), (28561.0/56430.0, k4), (-9.0/50.0, k5), (2.0/55.0, k6)]);
+    let fourth = advance(y, h, &[(25.0/216.0, k1), (1408.0/2565.0, k3), (2197.0/4104.0, k4), (-1.0/5.0, k5)]);
+    (fifth, (fifth.0 - fourth.0, fifth.1 - fourth.1))
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assert_vec3_eq;
+    use approx::assert_relative_eq;
+    use crate::utils::constants::Mp;
+
+    // Map on a 5 x 5 x 5 grid of 100 mm cells centred on the origin, with the given fields at each point
+    fn grid_map(field: impl Fn(Vec3) -> (Vec3, Vec3)) -> FieldMap {
+        let mut text = "x,y,z,Bx,By,Bz,Ex,Ey,Ez\n".to_string();
+        for i in 0..5 {
+            for j in 0..5 {
+                for k in 0..5 {
+                    let r = Vec3(i as f64, j as f64, k as f64) * 100.0 - Vec3(200.0, 200.0, 200.0);
+                    let (b, e) = field(r);
+                    text += &format!("{},{},{},{},{},{},{},{},{}\n", r.0, r.1, r.2, b.0, b.1, b.2, e.0, e.1, e.2);
+                }
+            }
+        }
+        FieldMap::parse_csv(&text).unwrap()
+    }
+
+    #[test]
+    fn test_field_uniform() {
+        let field = Field::Uniform(Vec3(0.0, 0.0, 1.0));
+        assert_eq!((field.magnetic(Vec3(1.0, 2.0, 3.0)), field.electric(Vec3(1.0, 2.0, 3.0))), (Vec3(0.0, 0.0, 1.0), Vec3(0.0, 0.0, 0.0)));
+        // Neutral particles go straight in any field
+        assert_eq!(field.track(Vec3(0.0, 0.0, 0.0), Vec3(3.0, 4.0, 0.0), 0.0, 0.0, 10.0), (Vec3(6.0, 8.0, 0.0), Vec3(3.0, 4.0, 0.0)));
+        assert_eq!(Field::default().magnetic(Vec3(1.0, 2.0, 3.0)), Vec3(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_field_map_parse() {
+        let text = "# x y z Bx By Bz\n0 0 0 0 0 1\n0 0 10 0 0 2\n0 5 0 0 0 3\n0 5 10 0 0 4\n";
+        let map = FieldMap::parse_csv(text).unwrap();
+        assert_eq!(map.size, [1, 2, 2]);
+        assert_eq!((map.origin, map.spacing.1, map.spacing.2), (Vec3(0.0, 0.0, 0.0), 5.0, 10.0));
+        assert_eq!(map.magnetic, vec![Vec3(0.0, 0.0, 1.0), Vec3(0.0, 0.0, 2.0), Vec3(0.0, 0.0, 3.0), Vec3(0.0, 0.0, 4.0)]);
+        assert_eq!(map.electric[3], Vec3(0.0, 0.0, 0.0));
+        // Missing, duplicate and irregular points
+        assert!(FieldMap::parse_csv("0 0 0 0 0 1\n0 0 10 0 0 2\n0 5 0 0 0 3\n").is_err());
+        assert!(FieldMap::parse_csv("0 0 0 0 0 1\n0 0 10 0 0 2\n0 0 10 0 0 3\n0 0 20 0 0 4\n").is_err());
+        assert!(FieldMap::parse_csv("0 0 0 0 0 1\n0 0 10 0 0 2\n0 0 30 0 0 3\n").is_err());
+        assert!(FieldMap::parse_csv("0 0 0 0 0\n").is_err());
+        assert!(FieldMap::parse_csv("x,y,z\n").is_err());
+
+        // Binary round trip
+        let map = grid_map(|r| (Vec3(0.0, r.0 * 1e-3, 1.0), Vec3(r.2, 0.0, 0.0)));
+        assert_eq!(FieldMap::from_binary(&map.to_binary()).unwrap(), map);
+        assert!(FieldMap::from_binary(&map.to_binary()[..100]).is_err());
+        assert!(FieldMap::from_binary(b"MAPF").is_err());
+        // Grids too large to be addressed, and grids with a spacing which is not positive
+        let mut huge = map.to_binary();
+        huge[4..16].fill(0xff);
+        assert!(FieldMap::from_binary(&huge).is_err());
+        for spacing in [0.0, -10.0, f64::NAN, f64::INFINITY] {
+            let map = FieldMap { spacing: Vec3(10.0, spacing, 10.0), ..map.clone() };
+            assert!(FieldMap::from_binary(&map.to_binary()).is_err());
+        }
+
+        // Directory of its own, so that tests running in parallel do not share files
+        let directory = std::env::temp_dir().join(format!("mount_charles_{}_test_field_map_parse", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("field.map");
+        std::fs::write(&path, map.to_binary()).unwrap();
+        assert_eq!(FieldMap::load(&path).unwrap(), map);
+        let path = directory.join("field.csv");
+        std::fs::write(&path, text).unwrap();
+        assert_eq!(FieldMap::load(&path).unwrap().size, [1, 2, 2]);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_field_map_interpolation() {
+        // Linear fields are interpolated exactly, and vanish outside the grid
+        let field = Field::Map(grid_map(|r| (Vec3(0.01 * r.0 + 1.0, 0.02 * r.1 - 0.03 * r.2, 0.5), Vec3(0.0, 0.0, 10.0 * r.0))));
+        for r in [Vec3(0.0, 0.0, 0.0), Vec3(-137.0, 58.2, 199.0), Vec3(200.0, -200.0, 200.0), Vec3(12.5, 150.0, -33.3)] {
+            assert_vec3_eq!(field.magnetic(r), Vec3(0.01 * r.0 + 1.0, 0.02 * r.1 - 0.03 * r.2, 0.5), 1e-9);
+            assert_vec3_eq!(field.electric(r), Vec3(0.0, 0.0, 10.0 * r.0), 1e-6);
+        }
+        assert_eq!(field.magnetic(Vec3(0.0, 0.0, 201.0)), Vec3(0.0, 0.0, 0.0));
+        assert_eq!(field.electric(Vec3(-250.0, 0.0, 0.0)), Vec3(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_field_map_track() {
+        // In a uniform map, the Runge-Kutta steps follow the exact helix
+        let uniform = Field::Map(grid_map(|_| (Vec3(0.2, 0.0, 1.0), Vec3(0.0, 0.0, 0.0))));
+        let (r, p, s) = (Vec3(0.0, 0.0, 0.0), Vec3(30.0, 40.0, 10.0), 150.0);
+        for q in [1.0, -1.0, 2.0] {
+            let (r_map, p_map) = uniform.track(r, p, Mp, q, s);
+            let (r_helix, p_helix) = helix(r, p, q, Vec3(0.2, 0.0, 1.0), s);
+            assert_vec3_eq!(r_map, r_helix, 1e-5);
+            assert_vec3_eq!(p_map, p_helix, 1e-6);
+            assert_relative_eq!(p_map.mag(), p.mag(), max_relative = 1e-7);
+        }
+
+        // An electric field along the motion changes the kinetic energy by q E s
+        let electric = Field::Map(grid_map(|_| (Vec3(0.0, 0.0, 0.0), Vec3(1e6, 0.0, 0.0))));
+        let energy = |p: Vec3| (p.dot(p) + Mp*Mp).sqrt();
+        let p = Vec3(200.0, 0.0, 0.0);
+        let (r_map, p_map) = electric.track(Vec3(-100.0, 0.0, 0.0), p, Mp, 1.0, 100.0);
+        assert_vec3_eq!(r_map, Vec3(0.0, 0.0, 0.0), 1e-9);
+        assert_relative_eq!(energy(p_map) - energy(p), 0.1, max_relative = 1e-6);
+        let (_, p_map) = electric.track(Vec3(-100.0, 0.0, 0.0), p, Mp, -1.0, 100.0);
+        assert_relative_eq!(energy(p_map) - energy(p), -0.1, max_relative = 1e-6);
+    }
+}
@@ -0,0 +1,740 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::path::Path;
+use std::sync::Arc;
+
+use roxmltree::{Document, Node, ParsingOptions};
+
+use crate::geometry::csg::{Boolean, Operation, Transformed};
+use crate::geometry::material::{Element, MATERIALS, Material};
+use crate::geometry::shape::{Cuboid, Cylinder, Shape, Sphere, SphericalShell};
+use crate::geometry::transform::{Rotation, Transform};
+use crate::geometry::volume::Volume;
+use crate::utils::vec3::Vec3;
+
+// Read a GDML geometry (the XML geometry format of Geant4): the world volume, with the hierarchy of volumes placed in
+// it. Materials, boxes, tubes, spheres and orbs, boolean solids and volume placements are supported.
+pub fn parse(text: &str) -> Result<Volume, String> {
+    let options = ParsingOptions { allow_dtd: true, ..ParsingOptions::default() };
+    let document = Document::parse_with_options(text, options).map_err(|e| e.to_string())?;
+    let mut reader = Reader::default();
+    let mut world = None;
+    for section in document.root_element().children().filter(Node::is_element) {
+        for node in section.children().filter(Node::is_element) {
+            let result = match section.tag_name().name() {
+                "define"    => reader.define(node),
+                "materials" => reader.material(node),
+                "solids"    => reader.solid(node),
+                "structure" => reader.volume(node),
+                "setup"     => {
+                    if node.tag_name().name() == "world" && world.is_none() {
+                        world = Some(reference(node)?);
+                    }
+                    Ok(())
+                }
+                _ => Ok(()),
+            };
+            result.map_err(|e| format!("<{} name=\"{}\">: {}", node.tag_name().name(), node.attribute("name").unwrap_or(""), e))?;
+        }
+    }
+    let world = world.ok_or("no world volume in the setup")?;
+    reader.volumes.remove(world).ok_or_else(|| format!("unknown volume '{world}'"))
+}
+
+// Read a GDML geometry from a file
+pub fn load(path: &Path) -> Result<Volume, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// Definitions read so far, by name
+#[derive(Default)]
+struct Reader {
+    constants: HashMap<String, f64>,
+    matrices: HashMap<String, (usize, Vec<f64>)>,  // number of columns and values
+    positions: HashMap<String, Vec3>,
+    rotations: HashMap<String, Rotation>,
+    elements: HashMap<String, (f64, f64)>,         // atomic number and atomic mass (g/mol) of elements and isotopes
+    materials: HashMap<String, Material>,
+    solids: HashMap<String, Arc<dyn Shape>>,
+    volumes: HashMap<String, Volume>,              // logical volumes, with their daughters placed in them
+}
+
+impl Reader {
+    fn evaluate(&self, expression: &str) -> Result<f64, String> {
+        let mut parser = Parser { text: expression.as_bytes(), position: 0, constants: &self.constants };
+        let value = parser.expression()?;
+        match parser.peek() {
+            None => Ok(value),
+            Some(_) => Err(format!("invalid expression '{expression}'")),
+        }
+    }
+
+    // Attribute of an element evaluated as an expression, or a default value if it is absent
+    fn value(&self, node: Node, attribute: &str, default: f64) -> Result<f64, String> {
+        node.attribute(attribute).map_or(Ok(default), |expression| self.evaluate(expression))
+    }
+
+    // Attribute of an element, in the unit given by another attribute (or a default unit)
+    fn quantity(&self, node: Node, attribute: &str, unit: &str, default_unit: &str) -> Result<f64, String> {
+        Ok(self.value(node, attribute, 0.0)? * self.evaluate(node.attribute(unit).unwrap_or(default_unit))?)
+    }
+
+    fn position(&self, node: Node) -> Result<Vec3, String> {
+        let length = |attribute| self.quantity(node, attribute, "unit", "mm");
+        Ok(Vec3(length("x")?, length("y")?, length("z")?))
+    }
+
+    // As in Geant4, the angles of a GDML rotation rotate the frame of a volume about x, then y, then z: the volume
+    // itself is rotated by the inverse rotation
+    fn rotation(&self, node: Node) -> Result<Rotation, String> {
+        let angle = |attribute| self.quantity(node, attribute, "unit", "rad");
+        Ok((Rotation::about_z(angle("z")?) * Rotation::about_y(angle("y")?) * Rotation::about_x(angle("x")?)).inverse())
+    }
+
+    // Placement given by the (prefixed) position and rotation children of an element, inline or by reference
+    fn placement(&self, node: Node, prefix: &str) -> Result<Transform, String> {
+        let mut placement = Transform::identity();
+        for child in node.children().filter(Node::is_element) {
+            match child.tag_name().name().strip_prefix(prefix) {
+                Some("position")    => placement.translation = self.position(child)?,
+                Some("positionref") => placement.translation = *lookup(&self.positions, "position", reference(child)?)?,
+                Some("rotation")    => placement.rotation = self.rotation(child)?,
+                Some("rotationref") => placement.rotation = *lookup(&self.rotations, "rotation", reference(child)?)?,
+                Some("scale" | "scaleref") => return Err("reflections are not supported".to_string()),
+                _ => {}
+            }
+        }
+        Ok(placement)
+    }
+
+    fn define(&mut self, node: Node) -> Result<(), String> {
+        let name = attribute(node, "name")?.to_string();
+        match node.tag_name().name() {
+            "constant" | "variable" => {
+                let value = self.evaluate(attribute(node, "value")?)?;
+                self.constants.insert(name, value);
+            }
+            "quantity" => {
+                let value = self.quantity(node, "value", "unit", "1")?;
+                self.constants.insert(name, value);
+            }
+            "expression" => {
+                let value = self.evaluate(node.text().unwrap_or(""))?;
+                self.constants.insert(name, value);
+            }
+            "position" => {
+                let position = self.position(node)?;
+                self.positions.insert(name, position);
+            }
+            "rotation" => {
+                let rotation = self.rotation(node)?;
+                self.rotations.insert(name, rotation);
+            }
+            "matrix" => {
+                let columns = self.value(node, "coldim", 1.0)? as usize;
+                let values = attribute(node, "values")?.split_whitespace().map(|value| self.evaluate(value)).collect::<Result<Vec<f64>, String>>()?;
+                self.matrices.insert(name, (columns.max(1), values));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // Atomic mass given by the atom child of an element (g/mol)
+    fn atomic_mass(&self, node: Node) -> Result<f64, String> {
+        let atom = child(node, "atom").ok_or("missing atomic mass")?;
+        self.quantity(atom, "value", "unit", "g/mole")
+    }
+
+    fn material(&mut self, node: Node) -> Result<(), String> {
+        let name = attribute(node, "name")?.to_string();
+        match node.tag_name().name() {
+            "isotope" => {
+                let za = (self.value(node, "Z", 0.0)?, self.atomic_mass(node)?);
+                self.elements.insert(name, za);
+            }
+            "element" if node.attribute("Z").is_some() => {
+                let za = (self.value(node, "Z", 0.0)?, self.atomic_mass(node)?);
+                self.elements.insert(name, za);
+            }
+            "element" => {
+                // Mixture of isotopes, with abundances
+                let (mut z, mut a) = (0.0, 0.0);
+                for fraction in node.children().filter(|child| child.has_tag_name("fraction")) {
+                    let (n, (zi, ai)) = (self.value(fraction, "n", 0.0)?, lookup(&self.elements, "isotope", reference(fraction)?)?);
+                    (z, a) = (zi.max(z), a + n * ai);
+                }
+                self.elements.insert(name, (z, a));
+            }
+            "material" => {
+                let material = self.new_material(node, &name)?;
+                self.materials.insert(name, material);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    #[allow(non_snake_case)]
+    fn new_material(&self, node: Node, name: &str) -> Result<Material, String> {
+        // Densities are evaluated in g/mm^3 like any other quantity, and converted to the g/cm^3 of the materials
+        let density = self.quantity(child(node, "D").ok_or("missing density")?, "value", "unit", "g/cm3")? * 1e3;
+
+        // Single element, mass fractions of elements and materials, or numbers of atoms of elements
+        let mut composition = vec![];
+        if node.attribute("Z").is_some() {
+            composition.push(Element { Z: self.value(node, "Z", 0.0)?, A: self.atomic_mass(node)?, fraction: 1.0 });
+        }
+        for child in node.children().filter(Node::is_element) {
+            let n = self.value(child, "n", 0.0)?;
+            match child.tag_name().name() {
+                "fraction" => match (self.elements.get(reference(child)?), self.materials.get(reference(child)?)) {
+                    (Some(&(Z, A)), _) => composition.push(Element { Z, A, fraction: n }),
+                    (None, Some(material)) => composition.extend(material.composition.iter().map(|e| Element { fraction: n * e.fraction, ..*e })),
+                    (None, None) => return Err(format!("unknown element or material '{}'", reference(child)?)),
+                },
+                "composite" => {
+                    let &(Z, A) = lookup(&self.elements, "element", reference(child)?)?;
+                    composition.push(Element { Z, A, fraction: n * A });
+                }
+                _ => {}
+            }
+        }
+        let total = composition.iter().map(|e| e.fraction).sum::<f64>();
+        if composition.is_empty() || total <= 0.0 {
+            return Err("missing composition".to_string());
+        }
+        composition.iter_mut().for_each(|e| e.fraction /= total);
+
+        let I = match child(node, "MEE") {
+            Some(mee) => self.quantity(mee, "value", "unit", "eV")?,
+            None => mean_excitation_energy(&composition),
+        };
+
+        // Optical properties: the mean of the RINDEX property, and scintillation if any scintillation property is
+        // given. Otherwise, those of the built-in material with the same name, if any, or opaque.
+        let properties = node.children().filter(|child| child.has_tag_name("property"))
+            .map(|property| Ok((attribute(property, "name")?, reference(property)?)))
+            .collect::<Result<Vec<(&str, &str)>, String>>()?;
+        let builtin = builtin_material(name);
+        let rindex = match properties.iter().find(|(property, _)| *property == "RINDEX") {
+            Some((_, matrix)) => {
+                let (columns, values) = lookup(&self.matrices, "matrix", matrix)?;
+                let indices = values.iter().skip(columns - 1).step_by(*columns).collect::<Vec<&f64>>();
+                Some(indices.iter().copied().sum::<f64>() / indices.len() as f64)
+            }
+            None => None,
+        };
+        let (n, transparency) = match (rindex, &builtin) {
+            (Some(n), _) => (n, 1.0),
+            (None, Some(builtin)) => (builtin.n, builtin.transparency),
+            (None, None) => (1.0, 0.0),
+        };
+        let scintillator = properties.iter().any(|(property, _)| property.starts_with("SCINTILLATION"))
+                        || builtin.is_some_and(|builtin| builtin.scintillator);
+
+        Ok(Material {
+            name: Cow::Owned(name.to_string()),
+            density,
+            composition: Cow::Owned(composition),
+            I,
+            n,
+            transparency,
+            scintillator,
+            stopping: vec![],
+        })
+    }
+
+    fn solid(&mut self, node: Node) -> Result<(), String> {
+        let name = attribute(node, "name")?.to_string();
+        let length = |attribute| self.quantity(node, attribute, "lunit", "mm");
+        let angle = |attribute, default| match node.attribute(attribute) {
+            Some(_) => self.quantity(node, attribute, "aunit", "rad"),
+            None => Ok(default),
+        };
+        let full = |attribute, range| -> Result<(), String> {
+            if (angle(attribute, range)? - range).abs() > 1e-9 {
+                return Err("segmented solids are not supported".to_string());
+            }
+            Ok(())
+        };
+        let origin = Vec3(0.0, 0.0, 0.0);
+
+        let solid: Arc<dyn Shape> = match node.tag_name().name() {
+            // GDML lengths of boxes and tubes are full lengths
+            "box" => Arc::new(Cuboid::new(origin, Vec3(length("x")?, length("y")?, length("z")?) / 2.0)),
+            "tube" => {
+                full("deltaphi", 2.0 * PI)?;
+                let (rmin, rmax, half_length) = (length("rmin")?, length("rmax")?, length("z")? / 2.0);
+                let axis = Vec3(0.0, 0.0, 1.0);
+                let outer = Cylinder::new(origin, axis, rmax, half_length);
+                if rmin > 0.0 {
+                    Arc::new(Boolean::subtraction(outer, Cylinder::new(origin, axis, rmin, half_length)))
+                } else {
+                    Arc::new(outer)
+                }
+            }
+            "orb" => Arc::new(Sphere::new(origin, length("r")?)),
+            "sphere" => {
+                full("deltaphi", 2.0 * PI)?;
+                full("starttheta", 0.0)?;
+                full("deltatheta", PI)?;
+                let (rmin, rmax) = (length("rmin")?, length("rmax")?);
+                if rmin > 0.0 {
+                    Arc::new(SphericalShell::new(origin, rmin, rmax))
+                } else {
+                    Arc::new(Sphere::new(origin, rmax))
+                }
+            }
+            tag @ ("union" | "intersection" | "subtraction") => {
+                let operation = match tag {
+                    "union"        => Operation::Union,
+                    "intersection" => Operation::Intersection,
+                    _              => Operation::Subtraction,
+                };
+                // The second solid (and optionally the first) is placed in the frame of the boolean solid
+                let part = |tag: &str, prefix: &str| -> Result<Arc<dyn Shape>, String> {
+                    let shape = lookup(&self.solids, "solid", reference(child(node, tag).ok_or(format!("missing {tag} solid"))?)?)?.clone();
+                    let transform = self.placement(node, prefix)?;
+                    if transform == Transform::identity() {
+                        Ok(shape)
+                    } else {
+                        Ok(Arc::new(Transformed { shape, transform }))
+                    }
+                };
+                Arc::new(Boolean { operation, first: part("first", "first")?, second: part("second", "")? })
+            }
+            tag => return Err(format!("unsupported solid {tag}")),
+        };
+        self.solids.insert(name, solid);
+        Ok(())
+    }
+
+    fn volume(&mut self, node: Node) -> Result<(), String> {
+        let name = attribute(node, "name")?;
+        if !node.has_tag_name("volume") {
+            return Err(format!("unsupported {}", node.tag_name().name()));
+        }
+        let material = reference(child(node, "materialref").ok_or("missing material")?)?;
+        let material = match self.materials.get(material) {
+            Some(material) => material.clone(),
+            None => builtin_material(material).ok_or_else(|| format!("unknown material '{material}'"))?,
+        };
+        let solid = lookup(&self.solids, "solid", reference(child(node, "solidref").ok_or("missing solid")?)?)?.clone();
+
+        let mut volume = Volume::transformed(name, solid, material, Transform::identity());
+        for physvol in node.children().filter(Node::is_element) {
+            match physvol.tag_name().name() {
+                "physvol" => {
+                    let logical = reference(child(physvol, "volumeref").ok_or("physvol without a volume")?)?;
+                    let mut daughter = lookup(&self.volumes, "volume", logical)?.clone();
+                    daughter.placement = self.placement(physvol, "")?;
+                    if let Some(name) = physvol.attribute("name") {
+                        daughter.name = name.to_string();
+                    }
+                    volume.add_daughter(daughter);
+                }
+                tag @ ("replicavol" | "paramvol" | "divisionvol") => return Err(format!("unsupported {tag}")),
+                _ => {}
+            }
+        }
+        self.volumes.insert(name.to_string(), volume);
+        Ok(())
+    }
+}
+
+fn attribute<'a>(node: Node<'a, '_>, name: &str) -> Result<&'a str, String> {
+    node.attribute(name).ok_or_else(|| format!("missing attribute {name}"))
+}
+
+// Name of the definition referenced by an element
+fn reference<'a>(node: Node<'a, '_>) -> Result<&'a str, String> {
+    attribute(node, "ref")
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, tag: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(tag))
+}
+
+fn lookup<'a, T>(definitions: &'a HashMap<String, T>, kind: &str, name: &str) -> Result<&'a T, String> {
+    definitions.get(name).ok_or_else(|| format!("unknown {kind} '{name}'"))
+}
+
+// Built-in material matching a material name, such as LAB, G4_WATER or G4_Pb (Geant4 NIST materials can be used in
+// GDML files without being defined)
+fn builtin_material(name: &str) -> Option<Material> {
+    let name = name.strip_prefix("G4_").unwrap_or(name).to_lowercase();
+    let name = match name.as_str() {
+        "pb" => "lead",
+        "fe" => "iron",
+        name => name,
+    };
+    MATERIALS.iter().find(|material| material.name.to_lowercase() == name).cloned()
+}
+
+// Mean excitation energy of a composition (MeV), from the Bragg additivity rule with I = 19.2 eV for hydrogen and
+// I = 16 Z^0.9 eV for the other elements
+#[allow(non_snake_case)]
+fn mean_excitation_energy(composition: &[Element]) -> f64 {
+    let log_I = |Z: f64| if Z <= 1.0 { f64::ln(19.2e-6) } else { f64::ln(16.0e-6 * Z.powf(0.9)) };
+    let electrons = composition.iter().map(|e| e.fraction * e.Z / e.A).sum::<f64>();
+    f64::exp(composition.iter().map(|e| e.fraction * e.Z / e.A * log_I(e.Z)).sum::<f64>() / electrons)
+}
+
+// Value of a unit or constant in the units of the simulation (mm, ns, rad, MeV, g and mol)
+fn unit(name: &str) -> Option<f64> {
+    Some(match name {
+        "pi"                => PI,
+        "nm"                => 1e-6,
+        "um"                => 1e-3,
+        "mm"                => 1.0,
+        "cm"                => 10.0,
+        "m"                 => 1e3,
+        "km"                => 1e6,
+        "mm3"               => 1.0,
+        "cm3"               => 1e3,
+        "m3"                => 1e9,
+        "rad"               => 1.0,
+        "mrad"              => 1e-3,
+        "deg" | "degree"    => PI / 180.0,
+        "eV"                => 1e-6,
+        "keV"               => 1e-3,
+        "MeV"               => 1.0,
+        "GeV"               => 1e3,
+        "mg"                => 1e-3,
+        "g"                 => 1.0,
+        "kg"                => 1e3,
+        "mole" | "mol"      => 1.0,
+        "ns"                => 1.0,
+        "s"                 => 1e9,
+        _ => return None,
+    })
+}
+
+// Recursive descent parser of the arithmetic expressions of GDML attributes: numbers, constants, units, + - * / ^,
+// parentheses and the usual functions
+struct Parser<'a> {
+    text: &'a [u8],
+    position: usize,
+    constants: &'a HashMap<String, f64>,
+}
+
+impl Parser<'_> {
+    // Next character, after any spaces
+    fn peek(&mut self) -> Option<u8> {
+        while self.text.get(self.position).is_some_and(u8::is_ascii_whitespace) {
+            self.position += 1;
+        }
+        self.text.get(self.position).copied()
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), String> {
+        if self.peek() != Some(c) {
+            return Err(format!("expected '{}' at position {}", c as char, self.position));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    // Sum of terms
+    fn expression(&mut self) -> Result<f64, String> {
+        let mut value = self.term()?;
+        while let Some(c @ (b'+' | b'-')) = self.peek() {
+            self.position += 1;
+            let term = self.term()?;
+            value = if c == b'+' { value + term } else { value - term };
+        }
+        Ok(value)
+    }
+
+    // Product of factors
+    fn term(&mut self) -> Result<f64, String> {
+        let mut value = self.factor()?;
+        while let Some(c @ (b'*' | b'/')) = self.peek() {
+            self.position += 1;
+            let factor = self.factor()?;
+            value = if c == b'*' { value * factor } else { value / factor };
+        }
+        Ok(value)
+    }
+
+    // Signed value, possibly raised to a power
+    fn factor(&mut self) -> Result<f64, String> {
+        match self.peek() {
+            Some(b'-') => { self.position += 1; Ok(-self.factor()?) }
+            Some(b'+') => { self.position += 1; self.factor() }
+            _ => {
+                let base = self.primary()?;
+                if self.peek() == Some(b'^') {
+                    self.position += 1;
+                    return Ok(base.powf(self.factor()?));
+                }
+                Ok(base)
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<f64, String> {
+        let start = self.position;
+        match self.peek() {
+            Some(b'(') => {
+                self.position += 1;
+                let value = self.expression()?;
+                self.expect(b')')?;
+                Ok(value)
+            }
+            Some(c) if c.is_ascii_digit() || c == b'.' => {
+                let start = self.position;
+                while let Some(&c) = self.text.get(self.position) {
+                    let exponent_sign = (c == b'+' || c == b'-') && matches!(self.text[self.position - 1], b'e' | b'E');
+                    if !(c.is_ascii_digit() || c == b'.' || c == b'e' || c == b'E' || exponent_sign) {
+                        break;
+                    }
+                    self.position += 1;
+                }
+                let number = std::str::from_utf8(&self.text[start..self.position]).unwrap();
+                number.parse::<f64>().map_err(|_| format!("invalid number '{number}'"))
+            }
+            Some(c) if c.is_ascii_alphabetic() || c == b'_' => {
+                let start = self.position;
+                while self.text.get(self.position).is_some_and(|&c| c.is_ascii_alphanumeric() || c == b'_') {
+                    self.position += 1;
+                }
+                let name = std::str::from_utf8(&self.text[start..self.position]).unwrap();
+                if self.peek() == Some(b'(') {
+                    self.position += 1;
+                    let argument = self.expression()?;
+                    self.expect(b')')?;
+                    let function = match name {
+                        "sqrt" => f64::sqrt,
+                        "exp"  => f64::exp,
+                        "log"  => f64::ln,
+                        "abs"  => f64::abs,
+                        "sin"  => f64::sin,
+                        "cos"  => f64::cos,
+                        "tan"  => f64::tan,
+                        "asin" => f64::asin,
+                        "acos" => f64::acos,
+                        "atan" => f64::atan,
+                        _ => return Err(format!("unknown function '{name}'")),
+                    };
+                    return Ok(function(argument));
+                }
+                self.constants.get(name).copied().or_else(|| unit(name)).ok_or_else(|| format!("unknown constant '{name}'"))
+            }
+            _ => Err(format!("unexpected end of expression at position {start}")),
+        }
+    }
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use crate::assert_vec3_eq;
+    use crate::geometry::material::{AIR, LEAD, WATER};
+    use crate::geometry::navigator::Navigator;
+
+    // Water tank with a steel lid and a scintillator ball hanging from a rotated bar, in air
+    const DETECTOR: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gdml xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:noNamespaceSchemaLocation="gdml.xsd">
+  <define>
+    <constant name="radius" value="30"/>
+    <quantity name="wall" value="0.5" unit="cm"/>
+    <position name="centre" x="0" y="0" z="0"/>
+    <position name="lid_position" z="radius + wall + 10" unit="mm"/>
+    <rotation name="quarter" z="90" unit="deg"/>
+    <matrix name="rindex" coldim="2" values="2.0e-6 1.47 3.0e-6 1.49"/>
+  </define>
+  <materials>
+    <element name="Hydrogen" formula="H" Z="1"><atom value="1.008"/></element>
+    <element name="Carbon" formula="C" Z="6"><atom value="12.011"/></element>
+    <element name="Oxygen" formula="O" Z="8"><atom value="15.999"/></element>
+    <material name="Water" state="liquid">
+      <D value="1.0" unit="g/cm3"/>
+      <composite n="2" ref="Hydrogen"/>
+      <composite n="1" ref="Oxygen"/>
+    </material>
+    <material name="Scintillator" state="liquid">
+      <property name="RINDEX" ref="rindex"/>
+      <property name="SCINTILLATIONYIELD" ref="rindex"/>
+      <D value="860" unit="kg/m3"/>
+      <MEE value="64.7" unit="eV"/>
+      <fraction n="0.123" ref="Hydrogen"/>
+      <fraction n="0.877" ref="Carbon"/>
+    </material>
+    <material name="Steel" Z="26">
+      <D value="7.874"/>
+      <atom value="55.845"/>
+    </material>
+  </materials>
+  <solids>
+    <box name="world_box" x="400" y="400" z="400"/>
+    <tube name="tank_tube" rmin="0" rmax="radius + wall" z="2*radius" deltaphi="360" aunit="deg"/>
+    <tube name="pipe_tube" rmin="2" rmax="3" z="50" deltaphi="2*pi"/>
+    <box name="lid_box" x="80" y="80" z="10"/>
+    <tube name="lid_hole" rmax="5" z="20"/>
+    <subtraction name="lid">
+      <first ref="lid_box"/>
+      <second ref="lid_hole"/>
+      <position name="hole_position" x="20"/>
+    </subtraction>
+    <orb name="ball" r="5"/>
+    <box name="bar" x="40" y="2" z="2"/>
+  </solids>
+  <structure>
+    <volume name="Ball">
+      <materialref ref="Scintillator"/>
+      <solidref ref="ball"/>
+    </volume>
+    <volume name="Bar">
+      <materialref ref="G4_Pb"/>
+      <solidref ref="bar"/>
+    </volume>
+    <volume name="Tank">
+      <materialref ref="Water"/>
+      <solidref ref="tank_tube"/>
+      <physvol name="bar">
+        <volumeref ref="Bar"/>
+        <position name="bar_position" x="0" y="10" z="0"/>
+        <rotationref ref="quarter"/>
+      </physvol>
+      <physvol>
+        <volumeref ref="Ball"/>
+        <position name="ball_position" x="-20" y="-10" z="0"/>
+      </physvol>
+    </volume>
+    <volume name="Lid">
+      <materialref ref="Steel"/>
+      <solidref ref="lid"/>
+    </volume>
+    <volume name="World">
+      <materialref ref="G4_AIR"/>
+      <solidref ref="world_box"/>
+      <physvol name="tank">
+        <volumeref ref="Tank"/>
+        <positionref ref="centre"/>
+      </physvol>
+      <physvol name="lid">
+        <volumeref ref="Lid"/>
+        <positionref ref="lid_position"/>
+      </physvol>
+    </volume>
+  </structure>
+  <setup name="Default" version="1.0">
+    <world ref="World"/>
+  </setup>
+</gdml>
+"#;
+
+    fn evaluate(expression: &str) -> Result<f64, String> {
+        let mut reader = Reader::default();
+        reader.constants.insert("radius".to_string(), 30.0);
+        reader.evaluate(expression)
+    }
+
+    #[test]
+    fn test_gdml_expressions() {
+        assert_relative_eq!(evaluate("1.5e-3").unwrap(), 1.5e-3);
+        assert_relative_eq!(evaluate(" 2 + 3*4 - 6/2 ").unwrap(), 11.0);
+        assert_relative_eq!(evaluate("-(2 + 3)^2").unwrap(), -25.0);
+        assert_relative_eq!(evaluate("2^3^2").unwrap(), 512.0);
+        assert_relative_eq!(evaluate("radius*2 + 1*cm").unwrap(), 70.0);
+        assert_relative_eq!(evaluate("sqrt(16) + cos(pi)").unwrap(), 3.0);
+        assert_relative_eq!(evaluate("90*deg").unwrap(), PI / 2.0);
+        assert_relative_eq!(evaluate("2*cm3").unwrap(), 2e3);
+        assert_relative_eq!(evaluate("kg/m3").unwrap(), evaluate("1e-3*g/cm3").unwrap());
+        assert!(evaluate("2 +").is_err());
+        assert!(evaluate("(2").is_err());
+        assert!(evaluate("2 3").is_err());
+        assert!(evaluate("height").is_err());
+        assert!(evaluate("cosh(1)").is_err());
+    }
+
+    #[test]
+    fn test_gdml_materials() {
+        let world = parse(DETECTOR).unwrap();
+        assert_eq!(world.material, AIR);
+        let tank = &world.daughters[0];
+        let water = &tank.material;
+        assert_eq!(water.name, "Water");
+        assert_relative_eq!(water.density, 1.0);
+        assert_relative_eq!(water.composition[0].fraction, WATER.composition[0].fraction, max_relative = 1e-3);
+        assert_relative_eq!(water.Z_over_A(), WATER.Z_over_A(), max_relative = 1e-4);
+        // Estimated mean excitation energy, and optical properties of the built-in water
+        assert_relative_eq!(water.I, WATER.I, max_relative = 0.02);
+        assert_eq!((water.n, water.transparency, water.scintillator), (WATER.n, WATER.transparency, false));
+
+        let scintillator = &tank.daughters[1].material;
+        assert_relative_eq!(scintillator.density, 0.86);
+        assert_relative_eq!(scintillator.I, 64.7e-6);
+        assert_relative_eq!(scintillator.n, 1.48);
+        assert_eq!((scintillator.transparency, scintillator.scintillator), (1.0, true));
+
+        assert_eq!(tank.daughters[0].material, LEAD);
+        let steel = &world.daughters[1].material;
+        assert_eq!(steel.composition.len(), 1);
+        assert_eq!((steel.composition[0].Z, steel.composition[0].A), (26.0, 55.845));
+        assert_eq!(steel.transparency, 0.0);
+    }
+
+    #[test]
+    fn test_gdml_structure() {
+        let world = parse(DETECTOR).unwrap();
+        assert_eq!(world.name, "World");
+        assert_relative_eq!(world.size(), 400.0);
+        assert_eq!(world.daughters.iter().map(|d| d.name.as_str()).collect::<Vec<&str>>(), vec!["tank", "lid"]);
+        assert_eq!(world.daughters[0].daughters.iter().map(|d| d.name.as_str()).collect::<Vec<&str>>(), vec!["bar", "Ball"]);
+        assert_vec3_eq!(world.daughters[1].placement.translation, Vec3(0.0, 0.0, 45.0));
+
+        let navigator = Navigator::new(&world);
+        let name = |r: Vec3| navigator.locate(r).map(|location| location.volume.name.clone());
+        assert_eq!(name(Vec3(20.0, 0.0, 0.0)).unwrap(), "tank");
+        assert_eq!(name(Vec3(34.0, 0.0, 29.0)).unwrap(), "tank");
+        assert_eq!(name(Vec3(36.0, 0.0, 0.0)).unwrap(), "World");
+        assert_eq!(name(Vec3(-20.0, -12.0, 1.0)).unwrap(), "Ball");
+        // The bar along x is rotated to lie along y
+        assert_eq!(name(Vec3(0.0, 25.0, 0.0)).unwrap(), "bar");
+        assert_eq!(name(Vec3(15.0, 10.0, 0.0)).unwrap(), "tank");
+        // The lid has a hole at x = 20
+        assert_eq!(name(Vec3(-20.0, 0.0, 45.0)).unwrap(), "lid");
+        assert_eq!(name(Vec3(20.0, 0.0, 45.0)).unwrap(), "World");
+        assert_eq!(name(Vec3(0.0, 0.0, 300.0)), None);
+
+        // Straight up through the tank, the air gap, and the hole in the lid
+        let up = Vec3(0.0, 0.0, 1.0);
+        let tank = navigator.locate(Vec3(20.0, 0.0, 0.0)).unwrap();
+        assert_relative_eq!(navigator.distance_to_boundary(&tank, Vec3(20.0, 0.0, 0.0), up), 30.0);
+        let air = navigator.locate_moving(Vec3(20.0, 0.0, 30.0), up).unwrap();
+        assert_relative_eq!(navigator.distance_to_boundary(&air, Vec3(20.0, 0.0, 30.0), up), 170.0);
+        let air = navigator.locate_moving(Vec3(-20.0, 0.0, 30.0), up).unwrap();
+        assert_relative_eq!(navigator.distance_to_boundary(&air, Vec3(-20.0, 0.0, 30.0), up), 10.0);
+    }
+
+    #[test]
+    fn test_gdml_errors() {
+        assert!(parse("<gdml><setup").is_err());
+        assert!(parse("<gdml/>").unwrap_err().contains("no world volume"));
+        assert!(parse(&DETECTOR.replace("<orb name=\"ball\" r=\"5\"/>", "<torus name=\"ball\"/>")).unwrap_err().contains("unsupported solid torus"));
+        assert!(parse(&DETECTOR.replace("deltaphi=\"2*pi\"", "deltaphi=\"pi\"")).unwrap_err().contains("segmented"));
+        assert!(parse(&DETECTOR.replace("G4_Pb", "G4_Cu")).unwrap_err().contains("unknown material 'G4_Cu'"));
+        assert!(parse(&DETECTOR.replace("radius + wall", "radius + height")).unwrap_err().contains("unknown constant 'height'"));
+        assert!(parse(&DETECTOR.replace("<world ref=\"World\"/>", "<world ref=\"Universe\"/>")).is_err());
+    }
+
+    #[test]
+    fn test_gdml_load() {
+        // Directory of its own, so that tests running in parallel do not share files
+        let directory = std::env::temp_dir().join(format!("mount_charles_{}_test_gdml_load", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("detector.gdml");
+        std::fs::write(&path, DETECTOR).unwrap();
+        assert_eq!(load(&path).unwrap().daughters.len(), 2);
+        assert!(load(&directory.join("missing.gdml")).is_err());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,252 @@
+use std::borrow::Cow;
+use std::path::Path;
+
+use rand::Rng;
+
+use crate::utils::constants::N_A;
+use crate::utils::stopping::{Projectile, StoppingTable};
+
+// Element of a material, with its mass fraction
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[allow(non_snake_case)]
+pub struct Element {
+    pub Z: f64,         // atomic number
+    pub A: f64,         // atomic mass (g/mol)
+    pub fraction: f64,  // mass fraction in the material
+}
+
+// Material
+#[derive(Debug, Clone, PartialEq)]
+#[allow(non_snake_case)]
+pub struct Material {
+    pub name: Cow<'static, str>,
+    pub density: f64,                          // density (g/cm^3)
+    pub composition: Cow<'static, [Element]>,  // elements, with mass fractions summing to 1
+    pub I: f64,                                // mean excitation energy (MeV)
+    pub n: f64,                                // refractive index
+    pub transparency: f64,                     // optical absorption and scattering lengths relative to water (0 if opaque)
+    pub scintillator: bool,                    // whether charged particles produce scintillation light in the material
+    pub stopping: Vec<StoppingTable>,          // tabulated stopping powers (the built-in fits are used for water otherwise)
+}
+
+// Elements, named by their chemical symbol: atomic number and molar mass (g/mol)
+const H: (f64, f64) = (1.0, 1.008);
+const C: (f64, f64) = (6.0, 12.011);
+const N: (f64, f64) = (7.0, 14.007);
+const O: (f64, f64) = (8.0, 15.999);
+#[allow(non_upper_case_globals)]
+const Ar: (f64, f64) = (18.0, 39.948);
+#[allow(non_upper_case_globals)]
+const Fe: (f64, f64) = (26.0, 55.845);
+#[allow(non_upper_case_globals)]
+const Pb: (f64, f64) = (82.0, 207.2);
+
+const fn element(za: (f64, f64), fraction: f64) -> Element {
+    Element { Z: za.0, A: za.1, fraction }
+}
+
+pub const WATER: Material = Material {
+    name: Cow::Borrowed("water"),
+    density: 1.0,
+    composition: Cow::Borrowed(&[element(H, 0.111894), element(O, 0.888106)]),
+    I: 75.0e-6,
+    n: 1.33,
+    transparency: 1.0,
+    scintillator: false,
+    stopping: Vec::new(),
+};
+
+// Linear alkylbenzene (C18H30)
+pub const LAB: Material = Material {
+    name: Cow::Borrowed("LAB"),
+    density: 0.86,
+    composition: Cow::Borrowed(&[element(H, 0.122706), element(C, 0.877294)]),
+    I: 64.7e-6,
+    n: 1.48,
+    transparency: 0.1,
+    scintillator: true,
+    stopping: Vec::new(),
+};
+
+pub const POLYSTYRENE: Material = Material {
+    name: Cow::Borrowed("polystyrene"),
+    density: 1.06,
+    composition: Cow::Borrowed(&[element(H, 0.077418), element(C, 0.922582)]),
+    I: 68.7e-6,
+    n: 1.59,
+    transparency: 0.02,
+    scintillator: false,
+    stopping: Vec::new(),
+};
+
+pub const LEAD: Material = Material {
+    name: Cow::Borrowed("lead"),
+    density: 11.35,
+    composition: Cow::Borrowed(&[element(Pb, 1.0)]),
+    I: 823.0e-6,
+    n: 1.0,
+    transparency: 0.0,
+    scintillator: false,
+    stopping: Vec::new(),
+};
+
+pub const IRON: Material = Material {
+    name: Cow::Borrowed("iron"),
+    density: 7.874,
+    composition: Cow::Borrowed(&[element(Fe, 1.0)]),
+    I: 286.0e-6,
+    n: 1.0,
+    transparency: 0.0,
+    scintillator: false,
+    stopping: Vec::new(),
+};
+
+// Dry air at sea level
+pub const AIR: Material = Material {
+    name: Cow::Borrowed("air"),
+    density: 1.205e-3,
+    composition: Cow::Borrowed(&[element(C, 0.000124), element(N, 0.755267), element(O, 0.231781), element(Ar, 0.012827)]),
+    I: 85.7e-6,
+    n: 1.0003,
+    transparency: 300.0,
+    scintillator: false,
+    stopping: Vec::new(),
+};
+
+pub const MATERIALS: [Material; 6] = [WATER, LAB, POLYSTYRENE, LEAD, IRON, AIR];
+
+impl Material {
+    // Built-in material from its name
+    pub fn from_name(name: &str) -> Option<Material> {
+        MATERIALS.iter().find(|material| material.name == name).cloned()
+    }
+
+    // Tabulated stopping powers for a projectile, if any
+    pub fn stopping_table(&self, projectile: Projectile) -> Option<&StoppingTable> {
+        self.stopping.iter().find(|table| table.projectile == projectile)
+    }
+
+    // Add a stopping power table, replacing the previous one for the same projectile
+    pub fn add_stopping_table(&mut self, table: StoppingTable) {
+        self.stopping.retain(|other| other.projectile != table.projectile);
+        self.stopping.push(table);
+    }
+
+    // Load the stopping power tables of the material from a directory, which can contain the NIST outputs
+    // <name>_estar.txt, <name>_pstar.txt and <name>_astar.txt. Returns the number of tables loaded.
+    // Not available in the browser, which has no filesystem: the built-in fits are used there.
+    pub fn load_stopping_tables(&mut self, directory: &Path) -> Result<usize, String> {
+        let mut count = 0;
+        for program in ["estar", "pstar", "astar"] {
+            let path = directory.join(format!("{}_{}.txt", self.name.to_lowercase(), program));
+            if !path.exists() {
+                continue;
+            }
+            let text = std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            self.add_stopping_table(StoppingTable::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?);
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    // Ratio of the atomic number to the atomic mass, averaged over the composition (mol/g)
+    #[allow(non_snake_case)]
+    pub fn Z_over_A(&self) -> f64 {
+        self.composition.iter().map(|e| e.fraction * e.Z / e.A).sum()
+    }
+
+    // Atomic number averaged over the electrons of the material
+    #[allow(non_snake_case)]
+    pub fn Z_eff(&self) -> f64 {
+        self.composition.iter().map(|e| e.fraction * e.Z / e.A * e.Z).sum::<f64>() / self.Z_over_A()
+    }
+
+    // Electron density (1/mm^3)
+    pub fn electron_density(&self) -> f64 {
+        N_A * self.density * self.Z_over_A() * 1e-3
+    }
+
+    // Radiation length (mm), from the approximation of the radiation length of each element
+    // X0 = 716.4 A / (Z (Z + 1) ln(287 / sqrt(Z))) g/cm^2, combined with 1/X0 = sum(w_i / X0_i)
+    pub fn radiation_length(&self) -> f64 {
+        let inverse = self.composition.iter().map(|e| {
+            e.fraction * e.Z * (e.Z + 1.0) * f64::ln(287.0 / e.Z.sqrt()) / (716.4 * e.A)
+        }).sum::<f64>();
+        10.0 / (inverse * self.density)
+    }
+
+    // Sample an element of the material, with a probability proportional to its number of atoms times a weight per
+    // atom (e.g. a cross section)
+    pub fn sample_element(&self, rng: &mut impl Rng, weight: impl Fn(&Element) -> f64) -> &Element {
+        let weights = self.composition.iter().map(|e| e.fraction / e.A * weight(e)).collect::<Vec<f64>>();
+        let mut r = rng.random::<f64>() * weights.iter().sum::<f64>();
+        for (element, w) in self.composition.iter().zip(weights) {
+            if r < w {
+                return element;
+            }
+            r -= w;
+        }
+        self.composition.last().expect("Material without composition.")
+    }
+
+    // Sum of w Z^n / A over the composition, for cross sections per atom scaling as Z^n (mol/g)
+    #[allow(non_snake_case)]
+    pub fn Z_power_over_A(&self, n: f64) -> f64 {
+        self.composition.iter().map(|e| e.fraction * e.Z.powf(n) / e.A).sum()
+    }
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use crate::utils::stopping::tests::{ESTAR_WATER, PSTAR_WATER};
+
+    #[test]
+    fn test_material_composition() {
+        for material in MATERIALS {
+            assert_relative_eq!(material.composition.iter().map(|e| e.fraction).sum::<f64>(), 1.0, max_relative = 1e-4);
+            assert_eq!(Material::from_name(&material.name), Some(material));
+        }
+        assert_eq!(Material::from_name("unobtainium"), None);
+        assert_relative_eq!(WATER.Z_over_A(), 0.5551, max_relative = 1e-3);
+        assert_relative_eq!(LEAD.Z_eff(), 82.0);
+        assert_relative_eq!(WATER.electron_density(), 3.343e20, max_relative = 1e-3);
+    }
+
+    #[test]
+    fn test_material_radiation_length() {
+        // PDG values
+        assert_relative_eq!(WATER.radiation_length(), 360.8, max_relative = 0.01);
+        assert_relative_eq!(POLYSTYRENE.radiation_length(), 413.1, max_relative = 0.02);
+        assert_relative_eq!(LEAD.radiation_length(), 5.612, max_relative = 0.02);
+        assert_relative_eq!(IRON.radiation_length(), 17.57, max_relative = 0.03);
+        assert_relative_eq!(AIR.radiation_length(), 3.039e5, max_relative = 0.01);
+    }
+
+    #[test]
+    fn test_material_load_stopping_tables() {
+        // Directory of its own, so that tests running in parallel do not share files
+        let directory = std::env::temp_dir().join(format!("mount_charles_{}_test_material_load_stopping_tables", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("water_estar.txt"), ESTAR_WATER).unwrap();
+        std::fs::write(directory.join("water_pstar.txt"), PSTAR_WATER).unwrap();
+        std::fs::write(directory.join("lab_estar.txt"), "not a table").unwrap();
+
+        let mut water = WATER.clone();
+        assert_eq!(water.stopping_table(Projectile::Electron), None);
+        assert_eq!(water.load_stopping_tables(&directory), Ok(2));
+        assert_eq!(water.stopping_table(Projectile::Electron).unwrap().energies.len(), 5);
+        assert_eq!(water.stopping_table(Projectile::Proton).unwrap().energies.len(), 4);
+        assert_eq!(water.stopping_table(Projectile::Alpha), None);
+        // Reloading replaces the tables
+        assert_eq!(water.load_stopping_tables(&directory), Ok(2));
+        assert_eq!(water.stopping.len(), 2);
+
+        assert!(LAB.clone().load_stopping_tables(&directory).is_err());
+        assert_eq!(LEAD.clone().load_stopping_tables(&directory), Ok(0));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -1 +1,9 @@
+pub mod material;
+pub mod shape;
+pub mod transform;
+pub mod field;
+pub mod csg;
 pub mod volume;
+pub mod navigator;
+pub mod gdml;
+pub mod fibre;
@@ -0,0 +1,191 @@
+use crate::geometry::transform::Transform;
+use crate::geometry::volume::Volume;
+use crate::utils::vec3::Vec3;
+
+// Distance by which a point on a boundary is moved along its direction to find the volume it is entering (mm)
+const PUSH: f64 = 1e-9;
+
+// Volume of the hierarchy in which a point lies
+#[derive(Debug, Clone, Copy)]
+pub struct Location<'a> {
+    pub volume: &'a Volume,
+    pub frame: Transform,  // placement of the volume in the frame of the world volume
+    pub depth: usize,      // 0 for the world volume, 1 for its daughters, ...
+}
+
+impl PartialEq for Location<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.volume, other.volume)
+    }
+}
+
+// Navigation of points and rays through the volume hierarchy
+pub struct Navigator<'a> {
+    pub world: &'a Volume,
+}
+
+impl<'a> Navigator<'a> {
+    pub fn new(world: &'a Volume) -> Self {
+        Navigator { world }
+    }
+
+    // Deepest volume containing a point, or None outside the world volume
+    pub fn locate(&self, r: Vec3) -> Option<Location<'a>> {
+        if !self.world.solid.contains(self.world.placement.to_local(r)) {
+            return None;
+        }
+        let mut location = Location { volume: self.world, frame: self.world.placement, depth: 0 };
+        loop {
+            let local = location.frame.to_local(r);
+            let Some(daughter) = location.volume.daughters.iter().find(|d| d.solid.contains(d.placement.to_local(local))) else {
+                return Some(location);
+            };
+            location = Location { volume: daughter, frame: location.frame * daughter.placement, depth: location.depth + 1 };
+        }
+    }
+
+    // Volume in which a particle at r moving along dir is: on a boundary, this is the volume it is entering
+    pub fn locate_moving(&self, r: Vec3, dir: Vec3) -> Option<Location<'a>> {
+        self.locate(r + dir * PUSH)
+    }
+
+    // Distance from a point in a volume to the next boundary along a direction, either leaving the volume or entering
+    // one of its daughters. The step is never shorter than the push used to locate points on boundaries.
+    pub fn distance_to_boundary(&self, location: &Location, r: Vec3, dir: Vec3) -> f64 {
+        let (local, dir) = (location.frame.to_local(r), location.frame.direction_to_local(dir));
+        location.volume.daughters.iter()
+            .map(|d| d.solid.distance_to_in(d.placement.to_local(local), d.placement.direction_to_local(dir)))
+            .fold(location.volume.solid.distance_to_out(local, dir), f64::min)
+            .max(PUSH)
+    }
+
+    // Normal of the boundary at r between the volume a particle leaves and the one it enters (None outside the world),
+    // pointing into the volume it enters
+    pub fn normal(&self, from: &Location, to: Option<&Location>, r: Vec3) -> Vec3 {
+        match to {
+            Some(to) if to.depth > from.depth => -to.frame.direction_to_parent(to.volume.solid.normal(to.frame.to_local(r))),
+            _ => from.frame.direction_to_parent(from.volume.solid.normal(from.frame.to_local(r))),
+        }
+    }
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use crate::assert_vec3_eq;
+    use crate::geometry::material::{AIR, LAB, POLYSTYRENE, WATER};
+    use crate::geometry::shape::{Cuboid, Cylinder, Sphere, SphericalShell};
+    use crate::geometry::transform::Rotation;
+    use std::f64::consts::PI;
+    use std::sync::Arc;
+
+    // Scintillator tank inside a water shield inside air
+    fn detector() -> Volume {
+        let mut world = Volume::new(1000.0, AIR);
+        let mut shield = Volume::placed("shield", Cylinder::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 1.0), 300.0, 400.0), WATER, Vec3(0.0, 0.0, 50.0));
+        shield.add_daughter(Volume::placed("tank", Sphere::new(Vec3(0.0, 0.0, 0.0), 100.0), LAB, Vec3(0.0, 0.0, -50.0)));
+        world.add_daughter(shield);
+        world.add_daughter(Volume::placed("box", Cuboid::new(Vec3(0.0, 0.0, 0.0), Vec3(50.0, 50.0, 10.0)), WATER, Vec3(400.0, 0.0, 0.0)));
+        world
+    }
+
+    #[test]
+    fn test_navigator_locate() {
+        let world = detector();
+        let navigator = Navigator::new(&world);
+        assert_eq!(navigator.locate(Vec3(0.0, 0.0, 0.0)).unwrap().volume.name, "tank");
+        assert_eq!(navigator.locate(Vec3(0.0, 0.0, 0.0)).unwrap().depth, 2);
+        assert_vec3_eq!(navigator.locate(Vec3(0.0, 0.0, 0.0)).unwrap().frame.translation, Vec3(0.0, 0.0, 0.0));
+        assert_eq!(navigator.locate(Vec3(0.0, 0.0, 200.0)).unwrap().volume.name, "shield");
+        assert_eq!(navigator.locate(Vec3(0.0, 0.0, 470.0)).unwrap().volume.name, "world");
+        assert_eq!(navigator.locate(Vec3(420.0, 30.0, 5.0)).unwrap().volume.material, WATER);
+        assert!(navigator.locate(Vec3(0.0, 0.0, 600.0)).is_none());
+        // On the surface of the tank, the volume depends on the direction
+        assert_eq!(navigator.locate_moving(Vec3(0.0, 0.0, 100.0), Vec3(0.0, 0.0, -1.0)).unwrap().volume.name, "tank");
+        assert_eq!(navigator.locate_moving(Vec3(0.0, 0.0, 100.0), Vec3(0.0, 0.0, 1.0)).unwrap().volume.name, "shield");
+    }
+
+    #[test]
+    fn test_navigator_distance_to_boundary() {
+        let world = detector();
+        let navigator = Navigator::new(&world);
+        let up = Vec3(0.0, 0.0, 1.0);
+        // Leaving the tank, then the shield, then the world
+        let tank = navigator.locate(Vec3(0.0, 0.0, 0.0)).unwrap();
+        assert_relative_eq!(navigator.distance_to_boundary(&tank, Vec3(0.0, 0.0, 0.0), up), 100.0);
+        let shield = navigator.locate_moving(Vec3(0.0, 0.0, 100.0), up).unwrap();
+        assert_relative_eq!(navigator.distance_to_boundary(&shield, Vec3(0.0, 0.0, 100.0), up), 350.0);
+        let air = navigator.locate_moving(Vec3(0.0, 0.0, 450.0), up).unwrap();
+        assert_relative_eq!(navigator.distance_to_boundary(&air, Vec3(0.0, 0.0, 450.0), up), 50.0);
+        // Entering the shield, then the tank
+        let air = navigator.locate(Vec3(0.0, 0.0, -450.0)).unwrap();
+        assert_relative_eq!(navigator.distance_to_boundary(&air, Vec3(0.0, 0.0, -450.0), up), 100.0);
+        let shield = navigator.locate_moving(Vec3(0.0, 0.0, -350.0), up).unwrap();
+        assert_relative_eq!(navigator.distance_to_boundary(&shield, Vec3(0.0, 0.0, -350.0), up), 250.0);
+        // Entering the box sideways
+        let air = navigator.locate(Vec3(400.0, -100.0, 0.0)).unwrap();
+        assert_relative_eq!(navigator.distance_to_boundary(&air, Vec3(400.0, -100.0, 0.0), Vec3(0.0, 1.0, 0.0)), 50.0);
+    }
+
+    #[test]
+    fn test_navigator_normal() {
+        let world = detector();
+        let navigator = Navigator::new(&world);
+        let r = Vec3(0.0, 0.0, 100.0);
+        let tank = navigator.locate_moving(r, Vec3(0.0, 0.0, -1.0)).unwrap();
+        let shield = navigator.locate_moving(r, Vec3(0.0, 0.0, 1.0)).unwrap();
+        assert_vec3_eq!(navigator.normal(&tank, Some(&shield), r), Vec3(0.0, 0.0, 1.0));
+        assert_vec3_eq!(navigator.normal(&shield, Some(&tank), r), Vec3(0.0, 0.0, -1.0));
+        let r = Vec3(0.0, 500.0, 0.0);
+        let air = navigator.locate(Vec3(0.0, 499.0, 0.0)).unwrap();
+        assert_vec3_eq!(navigator.normal(&air, None, r), Vec3(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn test_navigator_shapes() {
+        // Scintillator sphere in an acrylic-like vessel, next to a tilted cylinder
+        let mut world = Volume::new(1000.0, AIR);
+        world.add_daughter(Volume::placed("vessel", SphericalShell::new(Vec3(0.0, 0.0, 0.0), 100.0, 110.0), POLYSTYRENE, Vec3(0.0, 0.0, 0.0)));
+        world.add_daughter(Volume::placed("liquid", Sphere::new(Vec3(0.0, 0.0, 0.0), 100.0), LAB, Vec3(0.0, 0.0, 0.0)));
+        world.add_daughter(Volume::placed("pipe", Cylinder::new(Vec3(0.0, 0.0, 0.0), Vec3(1.0, 0.0, 1.0), 10.0, 50.0), WATER, Vec3(300.0, 0.0, 300.0)));
+        let navigator = Navigator::new(&world);
+        let x = Vec3(1.0, 0.0, 0.0);
+        assert_eq!(navigator.locate(Vec3(0.0, 0.0, 0.0)).unwrap().volume.name, "liquid");
+        assert_eq!(navigator.locate(Vec3(0.0, 105.0, 0.0)).unwrap().volume.name, "vessel");
+        assert_eq!(navigator.locate(Vec3(330.0, 0.0, 330.0)).unwrap().volume.name, "pipe");
+        assert_eq!(navigator.locate(Vec3(330.0, 0.0, 270.0)).unwrap().volume.name, "world");
+        let liquid = navigator.locate(Vec3(0.0, 0.0, 0.0)).unwrap();
+        assert_relative_eq!(navigator.distance_to_boundary(&liquid, Vec3(0.0, 0.0, 0.0), x), 100.0);
+        let vessel = navigator.locate_moving(Vec3(100.0, 0.0, 0.0), x).unwrap();
+        assert_eq!(vessel.volume.name, "vessel");
+        assert_relative_eq!(navigator.distance_to_boundary(&vessel, Vec3(100.0, 0.0, 0.0), x), 10.0);
+        // Along x, the pipe is entered where its surface crosses z = 300
+        let air = navigator.locate_moving(Vec3(110.0, 0.0, 300.0), x).unwrap();
+        assert_relative_eq!(navigator.distance_to_boundary(&air, Vec3(110.0, 0.0, 300.0), x), 190.0 - 10.0 * 2f64.sqrt(), max_relative = 1e-12);
+    }
+
+    #[test]
+    fn test_navigator_rotated() {
+        // Box along x rotated to lie along y, with a sphere placed at its +x end
+        let mut world = Volume::new(1000.0, AIR);
+        let placement = Transform::new(Rotation::about_z(PI / 2.0), Vec3(0.0, 0.0, 100.0));
+        let mut bar = Volume::transformed("bar", Arc::new(Cuboid::new(Vec3(0.0, 0.0, 0.0), Vec3(50.0, 10.0, 10.0))), WATER, placement);
+        bar.add_daughter(Volume::placed("ball", Sphere::new(Vec3(0.0, 0.0, 0.0), 5.0), LAB, Vec3(40.0, 0.0, 0.0)));
+        world.add_daughter(bar);
+        let navigator = Navigator::new(&world);
+        assert_eq!(navigator.locate(Vec3(0.0, 30.0, 100.0)).unwrap().volume.name, "bar");
+        assert_eq!(navigator.locate(Vec3(30.0, 0.0, 100.0)).unwrap().volume.name, "world");
+        let ball = navigator.locate(Vec3(0.0, 40.0, 100.0)).unwrap();
+        assert_eq!(ball.volume.name, "ball");
+        assert_relative_eq!((ball.frame.translation - Vec3(0.0, 40.0, 100.0)).mag(), 0.0, epsilon = 1e-12);
+        // Distances and normals are in the frame of the world
+        let bar = navigator.locate(Vec3(0.0, 0.0, 100.0)).unwrap();
+        assert_relative_eq!(navigator.distance_to_boundary(&bar, Vec3(0.0, 0.0, 100.0), Vec3(0.0, 1.0, 0.0)), 35.0, max_relative = 1e-12);
+        assert_relative_eq!(navigator.distance_to_boundary(&bar, Vec3(0.0, 0.0, 100.0), Vec3(1.0, 0.0, 0.0)), 10.0, max_relative = 1e-12);
+        assert_relative_eq!((navigator.normal(&bar, None, Vec3(10.0, 0.0, 100.0)) - Vec3(1.0, 0.0, 0.0)).mag(), 0.0, epsilon = 1e-12);
+        assert_relative_eq!((navigator.normal(&bar, Some(&ball), Vec3(0.0, 35.0, 100.0)) - Vec3(0.0, 1.0, 0.0)).mag(), 0.0, epsilon = 1e-12);
+    }
+}
@@ -0,0 +1,370 @@
+use std::fmt::Debug;
+
+use crate::utils::vec3::Vec3;
+
+// Rays which only cross a shape over less than this distance, or are about to leave it, do not enter it (mm)
+pub(crate) const TOLERANCE: f64 = 1e-9;
+
+// Shape of a volume, in the local frame of the volume (shareable between threads, as it is immutable)
+pub trait Shape: Debug + Send + Sync {
+    fn contains(&self, r: Vec3) -> bool;
+
+    // Distance from a point outside the shape to its surface, along a direction (infinite if the ray misses it)
+    fn distance_to_in(&self, r: Vec3, dir: Vec3) -> f64;
+
+    // Distance from a point inside the shape to its surface, along a direction
+    fn distance_to_out(&self, r: Vec3, dir: Vec3) -> f64;
+
+    // Outward normal of the surface closest to a point
+    fn normal(&self, r: Vec3) -> Vec3;
+
+    // Corners of the bounding box, with the lowest and highest coordinates (mm)
+    fn bounding_box(&self) -> (Vec3, Vec3);
+}
+
+// Distance to the surface of a convex shape from a point outside it, given the range of distances (t_in, t_out) along
+// the ray which lie inside the shape
+fn convex_distance_to_in(interval: Option<(f64, f64)>) -> f64 {
+    match interval {
+        Some((t_in, t_out)) if t_out > TOLERANCE && t_out - t_in > TOLERANCE => t_in.max(0.0),
+        _ => f64::INFINITY,
+    }
+}
+
+// Distance to the surface of a convex shape from a point inside it
+fn convex_distance_to_out(interval: Option<(f64, f64)>) -> f64 {
+    interval.map_or(0.0, |(_, t_out)| t_out.max(0.0))
+}
+
+// Intersect the range (t_in, t_out) with the range along a line in which a coordinate x (with direction d) lies
+// within [-h, h]
+fn slab(x: f64, d: f64, h: f64, t_in: f64, t_out: f64) -> Option<(f64, f64)> {
+    if d == 0.0 {
+        return if x.abs() <= h { Some((t_in, t_out)) } else { None };
+    }
+    let (t0, t1) = ((-h - x) / d, (h - x) / d);
+    let (t_in, t_out) = (t_in.max(t0.min(t1)), t_out.min(t0.max(t1)));
+    if t_in <= t_out { Some((t_in, t_out)) } else { None }
+}
+
+// Roots of a t^2 + 2 b t + c = 0, in increasing order
+fn quadratic(a: f64, b: f64, c: f64) -> Option<(f64, f64)> {
+    let discriminant = b*b - a*c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let sqrt = discriminant.sqrt();
+    Some(((-b - sqrt) / a, (-b + sqrt) / a))
+}
+
+// Axis-aligned box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cuboid {
+    pub centre: Vec3,  // (mm)
+    pub half: Vec3,    // half-lengths along x, y and z (mm)
+}
+
+impl Cuboid {
+    pub fn new(centre: Vec3, half: Vec3) -> Self {
+        Cuboid { centre, half }
+    }
+
+    // Cube of edge `size`, centred on the origin
+    pub fn cube(size: f64) -> Self {
+        Cuboid::new(Vec3(0.0, 0.0, 0.0), Vec3(size / 2.0, size / 2.0, size / 2.0))
+    }
+
+    fn interval(&self, r: Vec3, dir: Vec3) -> Option<(f64, f64)> {
+        let r = r - self.centre;
+        [(r.0, dir.0, self.half.0), (r.1, dir.1, self.half.1), (r.2, dir.2, self.half.2)].iter()
+            .try_fold((f64::NEG_INFINITY, f64::INFINITY), |(t_in, t_out), &(x, d, h)| slab(x, d, h, t_in, t_out))
+    }
+}
+
+impl Shape for Cuboid {
+    fn contains(&self, r: Vec3) -> bool {
+        let Vec3(x, y, z) = r - self.centre;
+        x.abs() <= self.half.0 && y.abs() <= self.half.1 && z.abs() <= self.half.2
+    }
+
+    fn distance_to_in(&self, r: Vec3, dir: Vec3) -> f64 {
+        convex_distance_to_in(self.interval(r, dir))
+    }
+
+    fn distance_to_out(&self, r: Vec3, dir: Vec3) -> f64 {
+        convex_distance_to_out(self.interval(r, dir))
+    }
+
+    fn normal(&self, r: Vec3) -> Vec3 {
+        let Vec3(x, y, z) = r - self.centre;
+        let gaps = [self.half.0 - x.abs(), self.half.1 - y.abs(), self.half.2 - z.abs()];
+        if gaps[0] <= gaps[1] && gaps[0] <= gaps[2] {
+            Vec3(x.signum(), 0.0, 0.0)
+        } else if gaps[1] <= gaps[2] {
+            Vec3(0.0, y.signum(), 0.0)
+        } else {
+            Vec3(0.0, 0.0, z.signum())
+        }
+    }
+
+    fn bounding_box(&self) -> (Vec3, Vec3) {
+        (self.centre - self.half, self.centre + self.half)
+    }
+}
+
+// Cylinder along any axis
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cylinder {
+    pub centre: Vec3,      // (mm)
+    pub axis: Vec3,        // unit vector along the axis
+    pub radius: f64,       // (mm)
+    pub half_length: f64,  // (mm)
+}
+
+impl Cylinder {
+    pub fn new(centre: Vec3, axis: Vec3, radius: f64, half_length: f64) -> Self {
+        Cylinder { centre, axis: axis.norm(), radius, half_length }
+    }
+
+    // Split a vector into its components along the axis and perpendicular to it
+    fn split(&self, v: Vec3) -> (f64, Vec3) {
+        let along = v.dot(self.axis);
+        (along, v - self.axis * along)
+    }
+
+    fn interval(&self, r: Vec3, dir: Vec3) -> Option<(f64, f64)> {
+        let (z, rho) = self.split(r - self.centre);
+        let (dz, d_rho) = self.split(dir);
+        let (t_in, t_out) = slab(z, dz, self.half_length, f64::NEG_INFINITY, f64::INFINITY)?;
+        let (a, b, c) = (d_rho.dot(d_rho), rho.dot(d_rho), rho.dot(rho) - self.radius*self.radius);
+        if a == 0.0 {
+            // Parallel to the axis
+            return if c <= 0.0 { Some((t_in, t_out)) } else { None };
+        }
+        let (t0, t1) = quadratic(a, b, c)?;
+        let (t_in, t_out) = (t_in.max(t0), t_out.min(t1));
+        if t_in <= t_out { Some((t_in, t_out)) } else { None }
+    }
+}
+
+impl Shape for Cylinder {
+    fn contains(&self, r: Vec3) -> bool {
+        let (z, rho) = self.split(r - self.centre);
+        rho.dot(rho) <= self.radius*self.radius && z.abs() <= self.half_length
+    }
+
+    fn distance_to_in(&self, r: Vec3, dir: Vec3) -> f64 {
+        convex_distance_to_in(self.interval(r, dir))
+    }
+
+    fn distance_to_out(&self, r: Vec3, dir: Vec3) -> f64 {
+        convex_distance_to_out(self.interval(r, dir))
+    }
+
+    fn normal(&self, r: Vec3) -> Vec3 {
+        let (z, rho) = self.split(r - self.centre);
+        if self.radius - rho.mag() <= self.half_length - z.abs() && rho.mag() > 0.0 {
+            rho.norm()
+        } else {
+            self.axis * z.signum()
+        }
+    }
+
+    fn bounding_box(&self) -> (Vec3, Vec3) {
+        // Extent of the end discs along each coordinate
+        let disc = |a: f64| self.radius * (1.0 - a*a).max(0.0).sqrt();
+        let half = Vec3(disc(self.axis.0), disc(self.axis.1), disc(self.axis.2))
+                 + Vec3(self.axis.0.abs(), self.axis.1.abs(), self.axis.2.abs()) * self.half_length;
+        (self.centre - half, self.centre + half)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sphere {
+    pub centre: Vec3,  // (mm)
+    pub radius: f64,   // (mm)
+}
+
+impl Sphere {
+    pub fn new(centre: Vec3, radius: f64) -> Self {
+        Sphere { centre, radius }
+    }
+
+    fn interval(&self, r: Vec3, dir: Vec3) -> Option<(f64, f64)> {
+        let r = r - self.centre;
+        quadratic(dir.dot(dir), r.dot(dir), r.dot(r) - self.radius*self.radius)
+    }
+}
+
+impl Shape for Sphere {
+    fn contains(&self, r: Vec3) -> bool {
+        let r = r - self.centre;
+        r.dot(r) <= self.radius*self.radius
+    }
+
+    fn distance_to_in(&self, r: Vec3, dir: Vec3) -> f64 {
+        convex_distance_to_in(self.interval(r, dir))
+    }
+
+    fn distance_to_out(&self, r: Vec3, dir: Vec3) -> f64 {
+        convex_distance_to_out(self.interval(r, dir))
+    }
+
+    fn normal(&self, r: Vec3) -> Vec3 {
+        (r - self.centre).norm()
+    }
+
+    fn bounding_box(&self) -> (Vec3, Vec3) {
+        let half = Vec3(self.radius, self.radius, self.radius);
+        (self.centre - half, self.centre + half)
+    }
+}
+
+// Region between two concentric spheres
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SphericalShell {
+    pub inner: Sphere,
+    pub outer: Sphere,
+}
+
+impl SphericalShell {
+    pub fn new(centre: Vec3, inner_radius: f64, outer_radius: f64) -> Self {
+        SphericalShell { inner: Sphere::new(centre, inner_radius), outer: Sphere::new(centre, outer_radius) }
+    }
+
+    // Whether a point is in the cavity (or on its surface)
+    fn in_cavity(&self, r: Vec3) -> bool {
+        (r - self.inner.centre).mag() <= self.inner.radius + TOLERANCE
+    }
+}
+
+impl Shape for SphericalShell {
+    fn contains(&self, r: Vec3) -> bool {
+        self.outer.contains(r) && (r - self.inner.centre).mag() >= self.inner.radius
+    }
+
+    fn distance_to_in(&self, r: Vec3, dir: Vec3) -> f64 {
+        if self.in_cavity(r) {
+            self.inner.distance_to_out(r, dir)
+        } else {
+            self.outer.distance_to_in(r, dir)
+        }
+    }
+
+    fn distance_to_out(&self, r: Vec3, dir: Vec3) -> f64 {
+        f64::min(self.outer.distance_to_out(r, dir), self.inner.distance_to_in(r, dir))
+    }
+
+    fn normal(&self, r: Vec3) -> Vec3 {
+        let d = (r - self.outer.centre).mag();
+        if self.outer.radius - d <= d - self.inner.radius {
+            self.outer.normal(r)
+        } else {
+            -self.inner.normal(r)
+        }
+    }
+
+    fn bounding_box(&self) -> (Vec3, Vec3) {
+        self.outer.bounding_box()
+    }
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use crate::assert_vec3_eq;
+
+    const ORIGIN: Vec3 = Vec3(0.0, 0.0, 0.0);
+
+    #[test]
+    fn test_shape_cuboid() {
+        let cuboid = Cuboid::new(Vec3(10.0, 0.0, 0.0), Vec3(1.0, 2.0, 3.0));
+        assert!(cuboid.contains(Vec3(10.5, -1.5, 2.9)));
+        assert!(!cuboid.contains(Vec3(0.5, -1.5, 2.9)));
+        assert_relative_eq!(cuboid.distance_to_out(Vec3(10.0, 0.0, 0.0), Vec3(0.0, 0.0, -1.0)), 3.0);
+        assert_relative_eq!(cuboid.distance_to_out(Vec3(10.0, 1.0, 0.0), Vec3(0.6, 0.8, 0.0)), 1.25);
+        assert_relative_eq!(cuboid.distance_to_out(Vec3(11.0, 0.0, 0.0), Vec3(1.0, 0.0, 0.0)), 0.0);
+        assert_relative_eq!(cuboid.distance_to_out(Vec3(9.0, 0.0, 0.0), Vec3(1.0, 0.0, 0.0)), 2.0);
+        assert_relative_eq!(cuboid.distance_to_in(ORIGIN, Vec3(1.0, 0.0, 0.0)), 9.0);
+        assert_eq!(cuboid.distance_to_in(ORIGIN, Vec3(-1.0, 0.0, 0.0)), f64::INFINITY);
+        assert_eq!(cuboid.distance_to_in(Vec3(0.0, 3.0, 0.0), Vec3(1.0, 0.0, 0.0)), f64::INFINITY);
+        assert_vec3_eq!(cuboid.normal(Vec3(10.9, 0.0, 2.5)), Vec3(1.0, 0.0, 0.0));
+        assert_vec3_eq!(cuboid.normal(Vec3(10.0, -2.0, 0.0)), Vec3(0.0, -1.0, 0.0));
+        assert_vec3_eq!(cuboid.bounding_box(), (Vec3(9.0, -2.0, -3.0), Vec3(11.0, 2.0, 3.0)));
+        assert_eq!(Cuboid::cube(4.0), Cuboid::new(ORIGIN, Vec3(2.0, 2.0, 2.0)));
+    }
+
+    #[test]
+    fn test_shape_cylinder() {
+        // Along z
+        let cylinder = Cylinder::new(ORIGIN, Vec3(0.0, 0.0, 1.0), 2.0, 5.0);
+        assert!(cylinder.contains(Vec3(1.2, -1.2, -4.0)));
+        assert!(!cylinder.contains(Vec3(1.5, 1.5, 0.0)));
+        assert!(!cylinder.contains(Vec3(0.0, 0.0, 5.5)));
+        assert_relative_eq!(cylinder.distance_to_out(ORIGIN, Vec3(0.0, 1.0, 0.0)), 2.0);
+        assert_relative_eq!(cylinder.distance_to_out(Vec3(0.0, 0.0, 1.0), Vec3(0.0, 0.0, 1.0)), 4.0);
+        assert_relative_eq!(cylinder.distance_to_out(Vec3(0.0, 0.0, 4.0), Vec3(0.6, 0.0, 0.8)), 1.25);
+        assert_relative_eq!(cylinder.distance_to_out(ORIGIN, Vec3(0.6, 0.0, 0.8)), 2.0 / 0.6);
+        assert_relative_eq!(cylinder.distance_to_in(Vec3(0.0, 10.0, 0.0), Vec3(0.0, -1.0, 0.0)), 8.0);
+        assert_relative_eq!(cylinder.distance_to_in(Vec3(0.0, 0.0, 10.0), Vec3(0.0, 0.0, -1.0)), 5.0);
+        assert_eq!(cylinder.distance_to_in(Vec3(3.0, 0.0, 10.0), Vec3(0.0, 0.0, -1.0)), f64::INFINITY);
+        assert_vec3_eq!(cylinder.normal(Vec3(0.0, -2.0, 1.0)), Vec3(0.0, -1.0, 0.0));
+        assert_vec3_eq!(cylinder.normal(Vec3(0.5, 0.0, -5.0)), Vec3(0.0, 0.0, -1.0));
+        assert_vec3_eq!(cylinder.bounding_box(), (Vec3(-2.0, -2.0, -5.0), Vec3(2.0, 2.0, 5.0)));
+
+        // Along (1, 1, 0), centred on (0, 0, 10)
+        let tilted = Cylinder::new(Vec3(0.0, 0.0, 10.0), Vec3(1.0, 1.0, 0.0), 1.0, 10.0);
+        assert_relative_eq!(tilted.axis.mag(), 1.0);
+        assert!(tilted.contains(Vec3(5.0, 5.0, 10.5)));
+        assert!(!tilted.contains(Vec3(5.0, -5.0, 10.0)));
+        assert!(!tilted.contains(Vec3(8.0, 8.0, 10.0)));
+        assert_relative_eq!(tilted.distance_to_out(Vec3(0.0, 0.0, 10.0), Vec3(0.0, 0.0, 1.0)), 1.0);
+        assert_relative_eq!(tilted.distance_to_out(Vec3(0.0, 0.0, 10.0), Vec3(1.0, 1.0, 0.0).norm()), 10.0);
+        assert_relative_eq!(tilted.distance_to_in(Vec3(3.0, 3.0, 0.0), Vec3(0.0, 0.0, 1.0)), 9.0);
+        assert_relative_eq!((tilted.normal(Vec3(3.0, 3.0, 11.0)) - Vec3(0.0, 0.0, 1.0)).mag(), 0.0, epsilon = 1e-12);
+        assert_relative_eq!((tilted.normal(Vec3(7.0, 7.0, 10.0)) - tilted.axis).mag(), 0.0, epsilon = 1e-12);
+        let (low, high) = tilted.bounding_box();
+        assert_relative_eq!(high.0, 10.0 / 2f64.sqrt() + 1.0 / 2f64.sqrt());
+        assert_relative_eq!(high.2 - low.2, 2.0);
+    }
+
+    #[test]
+    fn test_shape_sphere() {
+        let sphere = Sphere::new(Vec3(0.0, 0.0, 1.0), 3.0);
+        assert!(sphere.contains(Vec3(1.0, 2.0, -1.0)));
+        assert!(!sphere.contains(Vec3(2.0, 2.0, -1.0)));
+        assert_relative_eq!(sphere.distance_to_out(Vec3(0.0, 0.0, 1.0), Vec3(0.0, 0.6, 0.8)), 3.0);
+        assert_relative_eq!(sphere.distance_to_out(Vec3(0.0, 0.0, -2.0), Vec3(0.0, 0.0, 1.0)), 6.0);
+        assert_relative_eq!(sphere.distance_to_out(Vec3(1.0, 0.0, 1.0), Vec3(0.0, 1.0, 0.0)), 8f64.sqrt());
+        assert_relative_eq!(sphere.distance_to_in(Vec3(0.0, 0.0, -4.0), Vec3(0.0, 0.0, 1.0)), 2.0);
+        assert_eq!(sphere.distance_to_in(Vec3(0.0, 4.0, -4.0), Vec3(0.0, 0.0, 1.0)), f64::INFINITY);
+        // Leaving the surface, or tangent to it
+        assert_eq!(sphere.distance_to_in(Vec3(0.0, 0.0, 4.0), Vec3(0.0, 0.0, 1.0)), f64::INFINITY);
+        assert_eq!(sphere.distance_to_in(Vec3(0.0, 3.0, -4.0), Vec3(0.0, 0.0, 1.0)), f64::INFINITY);
+        assert_vec3_eq!(sphere.normal(Vec3(0.0, 3.0, 1.0)), Vec3(0.0, 1.0, 0.0));
+        assert_vec3_eq!(sphere.bounding_box(), (Vec3(-3.0, -3.0, -2.0), Vec3(3.0, 3.0, 4.0)));
+    }
+
+    #[test]
+    fn test_shape_spherical_shell() {
+        let shell = SphericalShell::new(ORIGIN, 2.0, 3.0);
+        assert!(shell.contains(Vec3(0.0, 2.5, 0.0)));
+        assert!(!shell.contains(Vec3(0.0, 1.5, 0.0)));
+        assert!(!shell.contains(Vec3(0.0, 3.5, 0.0)));
+        // From the outside, and from the cavity
+        assert_relative_eq!(shell.distance_to_in(Vec3(0.0, 0.0, -5.0), Vec3(0.0, 0.0, 1.0)), 2.0);
+        assert_relative_eq!(shell.distance_to_in(ORIGIN, Vec3(0.0, 0.0, 1.0)), 2.0);
+        assert_relative_eq!(shell.distance_to_in(Vec3(0.0, 0.0, -2.0), Vec3(0.0, 0.0, 1.0)), 4.0);
+        assert_eq!(shell.distance_to_in(Vec3(0.0, 0.0, 5.0), Vec3(0.0, 0.0, 1.0)), f64::INFINITY);
+        // Through the wall, either out of the shell or into the cavity
+        assert_relative_eq!(shell.distance_to_out(Vec3(0.0, 0.0, 2.5), Vec3(0.0, 0.0, 1.0)), 0.5);
+        assert_relative_eq!(shell.distance_to_out(Vec3(0.0, 0.0, 2.5), Vec3(0.0, 0.0, -1.0)), 0.5);
+        assert_relative_eq!(shell.distance_to_out(Vec3(0.0, 0.0, 2.0), Vec3(0.0, 0.0, 1.0)), 1.0);
+        assert_relative_eq!(shell.distance_to_out(Vec3(2.5, 0.0, 0.0), Vec3(0.0, 0.0, 1.0)), f64::sqrt(9.0 - 6.25));
+        assert_vec3_eq!(shell.normal(Vec3(0.0, 2.9, 0.0)), Vec3(0.0, 1.0, 0.0));
+        assert_vec3_eq!(shell.normal(Vec3(0.0, 2.1, 0.0)), Vec3(0.0, -1.0, 0.0));
+    }
+}
@@ -0,0 +1,151 @@
+use std::ops::Mul;
+
+use crate::utils::vec3::Vec3;
+
+// Rotation matrix, stored by rows
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rotation(pub [Vec3; 3]);
+
+impl Rotation {
+    pub fn identity() -> Self {
+        Rotation([Vec3(1.0, 0.0, 0.0), Vec3(0.0, 1.0, 0.0), Vec3(0.0, 0.0, 1.0)])
+    }
+
+    // Rotation by an angle (rad) about an axis, counterclockwise when looking down the axis
+    pub fn about_axis(axis: Vec3, angle: f64) -> Self {
+        let Vec3(x, y, z) = axis.norm();
+        let (s, c) = angle.sin_cos();
+        let t = 1.0 - c;
+        Rotation([
+            Vec3(t*x*x + c,   t*x*y - s*z, t*x*z + s*y),
+            Vec3(t*x*y + s*z, t*y*y + c,   t*y*z - s*x),
+            Vec3(t*x*z - s*y, t*y*z + s*x, t*z*z + c),
+        ])
+    }
+
+    pub fn about_x(angle: f64) -> Self {
+        Rotation::about_axis(Vec3(1.0, 0.0, 0.0), angle)
+    }
+
+    pub fn about_y(angle: f64) -> Self {
+        Rotation::about_axis(Vec3(0.0, 1.0, 0.0), angle)
+    }
+
+    pub fn about_z(angle: f64) -> Self {
+        Rotation::about_axis(Vec3(0.0, 0.0, 1.0), angle)
+    }
+
+    pub fn apply(&self, v: Vec3) -> Vec3 {
+        Vec3(self.0[0].dot(v), self.0[1].dot(v), self.0[2].dot(v))
+    }
+
+    // Inverse rotation (the transpose of the matrix)
+    pub fn inverse(&self) -> Self {
+        let [a, b, c] = self.0;
+        Rotation([Vec3(a.0, b.0, c.0), Vec3(a.1, b.1, c.1), Vec3(a.2, b.2, c.2)])
+    }
+}
+
+// Composition: (a * b) applies b first, then a
+impl Mul<Rotation> for Rotation {
+    type Output = Rotation;
+
+    fn mul(self, rhs: Rotation) -> Rotation {
+        let columns = rhs.inverse().0;
+        Rotation(self.0.map(|row| Vec3(row.dot(columns[0]), row.dot(columns[1]), row.dot(columns[2]))))
+    }
+}
+
+// Placement of a local frame in its parent frame: a point r in the local frame is at rotation * r + translation in
+// the parent frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub rotation: Rotation,
+    pub translation: Vec3,  // (mm)
+}
+
+impl Transform {
+    pub fn new(rotation: Rotation, translation: Vec3) -> Self {
+        Transform { rotation, translation }
+    }
+
+    pub fn identity() -> Self {
+        Transform::new(Rotation::identity(), Vec3(0.0, 0.0, 0.0))
+    }
+
+    pub fn translation(translation: Vec3) -> Self {
+        Transform::new(Rotation::identity(), translation)
+    }
+
+    // Point of the local frame in the parent frame
+    pub fn to_parent(&self, r: Vec3) -> Vec3 {
+        self.rotation.apply(r) + self.translation
+    }
+
+    // Point of the parent frame in the local frame
+    pub fn to_local(&self, r: Vec3) -> Vec3 {
+        self.rotation.inverse().apply(r - self.translation)
+    }
+
+    // Directions (and normals) are only rotated
+    pub fn direction_to_parent(&self, dir: Vec3) -> Vec3 {
+        self.rotation.apply(dir)
+    }
+
+    pub fn direction_to_local(&self, dir: Vec3) -> Vec3 {
+        self.rotation.inverse().apply(dir)
+    }
+}
+
+// Composition: (a * b) places a frame with b in a frame placed with a
+impl Mul<Transform> for Transform {
+    type Output = Transform;
+
+    fn mul(self, rhs: Transform) -> Transform {
+        Transform::new(self.rotation * rhs.rotation, self.to_parent(rhs.translation))
+    }
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assert_vec3_eq;
+    use approx::assert_relative_eq;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn test_transform_rotation() {
+        let x = Vec3(1.0, 0.0, 0.0);
+        let y = Vec3(0.0, 1.0, 0.0);
+        let z = Vec3(0.0, 0.0, 1.0);
+        assert_vec3_eq!(Rotation::about_z(PI / 2.0).apply(x), y, 1e-12);
+        assert_vec3_eq!(Rotation::about_x(PI / 2.0).apply(y), z, 1e-12);
+        assert_vec3_eq!(Rotation::about_y(PI / 2.0).apply(z), x, 1e-12);
+        assert_vec3_eq!(Rotation::about_axis(Vec3(1.0, 1.0, 1.0), 2.0 * PI / 3.0).apply(x), y, 1e-12);
+        let rotation = Rotation::about_axis(Vec3(0.3, -0.2, 0.9), 1.1);
+        let v = Vec3(0.5, 2.0, -1.0);
+        assert_relative_eq!(rotation.apply(v).mag(), v.mag(), max_relative = 1e-12);
+        assert_vec3_eq!(rotation.inverse().apply(rotation.apply(v)), v, 1e-12);
+        // Composition applies the right-hand rotation first
+        assert_vec3_eq!((Rotation::about_x(PI / 2.0) * Rotation::about_z(PI / 2.0)).apply(x), z, 1e-12);
+        assert_vec3_eq!((Rotation::about_z(PI / 2.0) * Rotation::about_x(PI / 2.0)).apply(x), y, 1e-12);
+    }
+
+    #[test]
+    fn test_transform_points() {
+        let transform = Transform::new(Rotation::about_z(PI / 2.0), Vec3(10.0, 0.0, 0.0));
+        assert_vec3_eq!(transform.to_parent(Vec3(1.0, 0.0, 0.0)), Vec3(10.0, 1.0, 0.0), 1e-12);
+        assert_vec3_eq!(transform.to_local(Vec3(10.0, 1.0, 0.0)), Vec3(1.0, 0.0, 0.0), 1e-12);
+        assert_vec3_eq!(transform.direction_to_parent(Vec3(1.0, 0.0, 0.0)), Vec3(0.0, 1.0, 0.0), 1e-12);
+        assert_vec3_eq!(transform.direction_to_local(Vec3(0.0, 1.0, 0.0)), Vec3(1.0, 0.0, 0.0), 1e-12);
+        assert_vec3_eq!(Transform::translation(Vec3(1.0, 2.0, 3.0)).to_parent(Vec3(0.0, 0.0, 0.0)), Vec3(1.0, 2.0, 3.0), 1e-12);
+        assert_eq!(Transform::identity() * transform, transform);
+        // Frame placed in a placed frame
+        let inner = Transform::new(Rotation::about_x(PI / 2.0), Vec3(0.0, 5.0, 0.0));
+        let r = Vec3(0.0, 1.0, 0.0);
+        assert_vec3_eq!((transform * inner).to_parent(r), transform.to_parent(inner.to_parent(r)), 1e-12);
+        assert_vec3_eq!((transform * inner).to_local(Vec3(3.0, 2.0, 1.0)), inner.to_local(transform.to_local(Vec3(3.0, 2.0, 1.0))), 1e-12);
+    }
+}
@@ -1,26 +1,104 @@
+use std::sync::Arc;
+
+use crate::geometry::material::Material;
+use crate::geometry::shape::{Cuboid, Shape};
+use crate::geometry::transform::Transform;
 use crate::particle::particle::Particle;
 use crate::utils::vec3::Vec3;
 
-#[derive(Clone)]
+// Face of the volume
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Face {
+    NegX,
+    PosX,
+    NegY,
+    PosY,
+    NegZ,
+    PosZ,
+}
+
+impl Face {
+    // Outward normal of the face
+    pub fn normal(self) -> Vec3 {
+        match self {
+            Face::NegX => Vec3(-1.0, 0.0, 0.0),
+            Face::PosX => Vec3(1.0, 0.0, 0.0),
+            Face::NegY => Vec3(0.0, -1.0, 0.0),
+            Face::PosY => Vec3(0.0, 1.0, 0.0),
+            Face::NegZ => Vec3(0.0, 0.0, -1.0),
+            Face::PosZ => Vec3(0.0, 0.0, 1.0),
+        }
+    }
+}
+
+// Volume of the geometry: a shape filled with a material, placed in its mother volume, and containing daughter
+// volumes (which must fit inside it without overlapping each other)
+#[derive(Debug, Clone)]
 pub struct Volume {
-    pub size: f64,  // cube edge length (mm)
-    pub X0: f64,    // radiation length (mm)
+    pub name: String,
+    pub solid: Arc<dyn Shape>,  // shape of the volume, in its own frame
+    pub material: Material,      // material filling the volume (outside its daughters)
+    pub placement: Transform,    // position and orientation of the volume in the frame of its mother
+    pub daughters: Vec<Volume>,
 }
 
 impl Volume {
-    pub fn new(s: f64, rad_len: f64) -> Self {
-        Volume { size: s, X0: rad_len }
+    // Cubic world volume of edge `s`, centred on the origin
+    pub fn new(s: f64, mat: Material) -> Self {
+        Volume::placed("world", Cuboid::cube(s), mat, Vec3(0.0, 0.0, 0.0))
+    }
+
+    // Volume placed at a position in its mother volume
+    pub fn placed(name: &str, solid: impl Shape + 'static, material: Material, position: Vec3) -> Self {
+        Volume::transformed(name, Arc::new(solid), material, Transform::translation(position))
+    }
+
+    // Volume placed at a position and rotated in its mother volume
+    pub fn transformed(name: &str, solid: Arc<dyn Shape>, material: Material, placement: Transform) -> Self {
+        Volume { name: name.to_string(), solid, material, placement, daughters: vec![] }
+    }
+
+    pub fn add_daughter(&mut self, daughter: Volume) {
+        self.daughters.push(daughter);
+    }
+
+    // Largest edge of the box bounding the volume (mm)
+    pub fn size(&self) -> f64 {
+        let (low, high) = self.solid.bounding_box();
+        let edges = high - low;
+        edges.0.max(edges.1).max(edges.2)
     }
 
     pub fn contains(&self, particle: &Particle) -> bool {
-        let Vec3(x, y, z) = particle.state.r;
-        let hs = self.size / 2.0;
-        if (-hs <= x && x <= hs) && (-hs <= y && y <= hs) && (-hs <= z && z <= hs) {
-            true
+        self.solid.contains(self.placement.to_local(particle.state.r))
+    }
+
+    // Distance from a point inside the volume to its boundary, along a direction
+    pub fn distance_to_out(&self, r: Vec3, dir: Vec3) -> f64 {
+        self.solid.distance_to_out(self.placement.to_local(r), self.placement.direction_to_local(dir))
+    }
+
+    // Face of the bounding box closest to a point
+    pub fn face(&self, r: Vec3) -> Face {
+        let (low, high) = self.solid.bounding_box();
+        let (centre, half) = ((low + high) / 2.0, (high - low) / 2.0);
+        let Vec3(x, y, z) = self.placement.to_local(r) - centre;
+        let (x, y, z) = (x / half.0, y / half.1, z / half.2);
+        if x.abs() >= y.abs() && x.abs() >= z.abs() {
+            if x < 0.0 { Face::NegX } else { Face::PosX }
+        } else if y.abs() >= z.abs() {
+            if y < 0.0 { Face::NegY } else { Face::PosY }
+        } else if z < 0.0 {
+            Face::NegZ
         } else {
-            false
+            Face::PosZ
         }
     }
+
+    // Outward normal of the surface closest to a point
+    pub fn normal(&self, r: Vec3) -> Vec3 {
+        self.placement.direction_to_parent(self.solid.normal(self.placement.to_local(r)))
+    }
 }
 
 
@@ -30,29 +108,70 @@ mod tests {
     use super::*;
     use approx::assert_relative_eq;
     use crate::particle::particle::ParticleType;
+    use crate::geometry::material::{AIR, WATER};
+    use crate::geometry::shape::Cylinder;
 
     #[test]
     fn test_volume_creation() {
-        let v1 = Volume::new(5.0, 50.0);
-        let v2 = Volume::new(15.0, 36.0);
-        let v3 = Volume::new(62.3, 52.0);
-        assert_relative_eq!(v1.size, 5.0);
-        assert_relative_eq!(v2.size, 15.0);
-        assert_relative_eq!(v3.size, 62.3);
+        let v1 = Volume::new(5.0, WATER);
+        let v2 = Volume::new(15.0, WATER);
+        let v3 = Volume::new(62.3, WATER);
+        assert_relative_eq!(v1.size(), 5.0);
+        assert_relative_eq!(v2.size(), 15.0);
+        assert_relative_eq!(v3.size(), 62.3);
+        assert_eq!(v1.name, "world");
+        assert!(v1.daughters.is_empty());
     }
 
     #[test]
     fn test_volume_contains() {
-        let v1 = Volume::new(10.0, 30.0);
-        let v2 = Volume::new(28.4, 44.2);
+        let v1 = Volume::new(10.0, WATER);
+        let v2 = Volume::new(28.4, WATER);
         let p1 = Particle::new(Vec3(1.0, 2.0, -3.0), Vec3(5.0, 0.0, 0.0), ParticleType::Electron);
         let p2 = Particle::new(Vec3(4.2, -1.5, 5.1), Vec3(3.4, -2.0, 0.7), ParticleType::Muon);
         let p3 = Particle::new(Vec3(20.1, -10.3, -9.7), Vec3(-100.0, 0.0, -52.1), ParticleType::Gamma);
-        assert_eq!(v1.contains(&p1), true);
-        assert_eq!(v1.contains(&p2), false);
-        assert_eq!(v1.contains(&p3), false);
-        assert_eq!(v2.contains(&p1), true);
-        assert_eq!(v2.contains(&p2), true);
-        assert_eq!(v2.contains(&p3), false);
+        assert!(v1.contains(&p1));
+        assert!(!v1.contains(&p2));
+        assert!(!v1.contains(&p3));
+        assert!(v2.contains(&p1));
+        assert!(v2.contains(&p2));
+        assert!(!v2.contains(&p3));
+    }
+
+    #[test]
+    fn test_volume_distance_to_out() {
+        let v = Volume::new(10.0, WATER);
+        assert_relative_eq!(v.distance_to_out(Vec3(0.0, 0.0, 0.0), Vec3(1.0, 0.0, 0.0)), 5.0);
+        assert_relative_eq!(v.distance_to_out(Vec3(1.0, 2.0, -3.0), Vec3(0.0, 0.0, -1.0)), 2.0);
+        assert_relative_eq!(v.distance_to_out(Vec3(0.0, 0.0, 0.0), Vec3(1.0, 1.0, 0.0).norm()), 5.0 * 2f64.sqrt());
+        assert_relative_eq!(v.distance_to_out(Vec3(4.0, 0.0, 0.0), Vec3(0.6, -0.8, 0.0)), 5.0/3.0);
+        assert_relative_eq!(v.distance_to_out(Vec3(5.0, 0.0, 0.0), Vec3(1.0, 0.0, 0.0)), 0.0);
+    }
+
+    #[test]
+    fn test_volume_normal() {
+        let v = Volume::new(10.0, WATER);
+        assert_eq!(v.normal(Vec3(5.2, 1.0, -3.0)), Vec3(1.0, 0.0, 0.0));
+        assert_eq!(v.normal(Vec3(0.5, -5.1, 4.9)), Vec3(0.0, -1.0, 0.0));
+        assert_eq!(v.normal(Vec3(2.0, 3.0, 6.0)), Vec3(0.0, 0.0, 1.0));
+        assert_eq!(v.face(Vec3(-5.0, 1.0, 1.0)), Face::NegX);
+        assert_eq!(v.face(Vec3(1.0, 2.0, -5.0)), Face::NegZ);
+        assert_eq!(v.face(Vec3(1.0, 5.0, 2.0)), Face::PosY);
+    }
+
+    #[test]
+    fn test_volume_placed() {
+        let mut world = Volume::new(100.0, AIR);
+        let tank = Volume::placed("tank", Cylinder::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 1.0), 10.0, 20.0), WATER, Vec3(0.0, 30.0, 0.0));
+        let p1 = Particle::new(Vec3(5.0, 25.0, -15.0), Vec3(5.0, 0.0, 0.0), ParticleType::Electron);
+        let p2 = Particle::new(Vec3(5.0, 15.0, -15.0), Vec3(5.0, 0.0, 0.0), ParticleType::Electron);
+        assert!(tank.contains(&p1));
+        assert!(!tank.contains(&p2));
+        assert_relative_eq!(tank.distance_to_out(Vec3(0.0, 30.0, 0.0), Vec3(0.0, 0.0, 1.0)), 20.0);
+        assert_eq!(tank.normal(Vec3(0.0, 40.0, 0.0)), Vec3(0.0, 1.0, 0.0));
+        assert_relative_eq!(tank.size(), 40.0);
+        world.add_daughter(tank);
+        assert_eq!(world.daughters[0].name, "tank");
+        assert_eq!(world.daughters[0].material, WATER);
     }
 }
@@ -6,8 +6,13 @@ pub mod sim;
 use wasm_bindgen::prelude::*;
 use serde_wasm_bindgen::to_value;
 use crate::sim::world::World;
+use crate::geometry::field::{Field, FieldMap};
+use crate::geometry::gdml;
+use crate::geometry::material::Material;
 use crate::geometry::volume::Volume;
-use crate::particle::particle::{Particle, ParticleType};
+use crate::geometry::fibre::{Fibre, FibreLattice};
+use crate::particle::particle::{Particle, ParticleType, PhotonMode, Scintillation};
+use crate::utils::stopping::StoppingTable;
 use crate::utils::vec3::Vec3;
 
 #[wasm_bindgen]
@@ -18,8 +23,9 @@ pub struct WASMWorld {
 #[wasm_bindgen]
 impl WASMWorld {
     #[wasm_bindgen(constructor)]
-    pub fn new(volume_size: f64, X0: f64, dt: f64, seed: u32) -> Self {
-        let volume = Volume::new(volume_size, X0);
+    pub fn new(volume_size: f64, material: &str, dt: f64, seed: u32) -> Self {
+        let material = Material::from_name(material).expect("unknown material");
+        let volume = Volume::new(volume_size, material);
         WASMWorld { world: World::new(vec![], volume, dt, seed as u64) }
     }
 
@@ -28,25 +34,117 @@ impl WASMWorld {
     }
 
     // Add particle to simulation: API will need to be reviewed
+    #[allow(clippy::too_many_arguments)]
     pub fn add_particle(&mut self, name: &str, x: f64, y: f64, z: f64, px: f64, py: f64, pz: f64) {
         let particle = match name {
             "e-"    => Particle::new(Vec3(x, y, z), Vec3(px, py, pz), ParticleType::Electron),
+            "e+"    => Particle::new(Vec3(x, y, z), Vec3(px, py, pz), ParticleType::Positron),
             "mu-"   => Particle::new(Vec3(x, y, z), Vec3(px, py, pz), ParticleType::Muon),
+            "mu+"   => Particle::new(Vec3(x, y, z), Vec3(px, py, pz), ParticleType::AntiMuon),
+            "pi+"   => Particle::new(Vec3(x, y, z), Vec3(px, py, pz), ParticleType::PionPlus),
+            "pi-"   => Particle::new(Vec3(x, y, z), Vec3(px, py, pz), ParticleType::PionMinus),
+            "p"     => Particle::new(Vec3(x, y, z), Vec3(px, py, pz), ParticleType::Proton),
             "gamma" => Particle::new(Vec3(x, y, z), Vec3(px, py, pz), ParticleType::Gamma),
+            "n"     => Particle::new(Vec3(x, y, z), Vec3(px, py, pz), ParticleType::Neutron),
             _       => panic!("Unknown particle type."),
         };
-        self.world.particles.push(particle);
+        self.world.add_particle(particle);
+    }
+
+    // Cherenkov light simulation mode: "off", "count" or "full"
+    pub fn set_cherenkov_mode(&mut self, mode: &str) {
+        self.world.cherenkov = photon_mode(mode);
+    }
+
+    pub fn get_cherenkov_count(&self) -> u64 {
+        self.world.cherenkov_count()
+    }
+
+    // Fill the volume with liquid scintillator (LAB), with the given simulation mode and light yield (photons/MeV)
+    pub fn set_scintillation(&mut self, mode: &str, light_yield: f64) {
+        self.world.scintillation = Scintillation { mode: photon_mode(mode), light_yield, ..Scintillation::LAB };
+    }
+
+    pub fn get_scintillation_count(&self) -> u64 {
+        self.world.scintillation_count()
+    }
+
+    // Detected optical photons, as a list of [x, y, z, t, wavelength]
+    pub fn get_photon_hits(&self) -> JsValue {
+        let serded_hits = self.world.hits.iter()
+                                         .map(|hit| vec![hit.position.0, hit.position.1, hit.position.2, hit.time, hit.wavelength])
+                                         .collect::<Vec<Vec<f64>>>();
+        to_value(&serded_hits).unwrap()
+    }
+
+    // Use a stopping power table (text output of NIST ESTAR, PSTAR or ASTAR) for the material of the volume, instead
+    // of the built-in fits. The browser has no filesystem, so the front end has to fetch the table itself.
+    pub fn add_stopping_table(&mut self, text: &str) {
+        let table = StoppingTable::parse(text).expect("invalid stopping power table");
+        self.world.volume.material.add_stopping_table(table);
+    }
+
+    // Fluctuations of the energy loss of the charged particles around its mean (off by default)
+    pub fn set_straggling(&mut self, straggling: bool) {
+        self.world.straggling = straggling;
+    }
+
+    // Uniform magnetic field (T), bending the charged particles
+    pub fn set_magnetic_field(&mut self, bx: f64, by: f64, bz: f64) {
+        self.world.field = Field::Uniform(Vec3(bx, by, bz));
+    }
+
+    // Magnetic and electric field maps on a grid, from the text of a CSV file (see FieldMap::parse_csv)
+    pub fn set_field_map_csv(&mut self, text: &str) {
+        self.world.field = Field::Map(FieldMap::parse_csv(text).expect("invalid field map"));
+    }
+
+    // Magnetic and electric field maps on a grid, from the bytes of a binary file (see FieldMap::from_binary)
+    pub fn set_field_map_binary(&mut self, bytes: &[u8]) {
+        self.world.field = Field::Map(FieldMap::from_binary(bytes).expect("invalid field map"));
+    }
+
+    // Replace the volume by the geometry described in a GDML file. As for the stopping power tables, the front end has
+    // to fetch the file itself.
+    pub fn set_geometry_gdml(&mut self, text: &str) {
+        self.world.volume = gdml::parse(text).expect("invalid GDML geometry");
+    }
+
+    // Fill the volume with a lattice of wavelength-shifting fibres along x, y and z (LiquidO)
+    pub fn set_fibres(&mut self, pitch: f64, capture_radius: f64, attenuation_length: f64) {
+        self.world.fibres = Some(FibreLattice::new(self.world.volume.size(), pitch, capture_radius, attenuation_length));
+    }
+
+    // Fibres which detected photons, with their direction, transverse position and list of hit times
+    pub fn get_fibre_hits(&self) -> JsValue {
+        let hit_fibres = match &self.world.fibres {
+            Some(fibres) => fibres.fibres.iter().filter(|fibre| !fibre.hits.is_empty()).collect::<Vec<&Fibre>>(),
+            None         => vec![],
+        };
+        to_value(&hit_fibres).unwrap()
     }
 
     pub fn has_alive_particles(&self) -> bool {
         self.world.has_alive_particles()
     }
 
-    pub fn get_particle_position_history(&self) -> JsValue {
-        let serded_positions = self.world.position_history.clone()
-                                                          .into_iter()
-                                                          .map(|r| vec![r.0, r.1, r.2])
-                                                          .collect::<Vec<Vec<f64>>>();
-        to_value(&serded_positions).unwrap()
+    pub fn get_time(&self) -> f64 {
+        self.world.time()
+    }
+
+    // Trajectories of all the particles: track ID, parent ID, species, creator process, and step points
+    // (position, time, momentum and deposited energy)
+    pub fn get_trajectories(&self) -> JsValue {
+        to_value(&self.world.trajectories).unwrap()
+    }
+}
+
+// Optical photon simulation mode from its name: "off", "count" or "full"
+fn photon_mode(mode: &str) -> PhotonMode {
+    match mode {
+        "off"   => PhotonMode::Off,
+        "count" => PhotonMode::Count,
+        "full"  => PhotonMode::Full,
+        _       => panic!("Unknown photon mode: {mode}"),
     }
 }
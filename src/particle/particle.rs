@@ -1,8 +1,19 @@
 use rand::Rng;
+use rand_distr::{Distribution, Normal, Poisson};
+use serde::Serialize;
 
 use crate::utils::vec3::Vec3;
-use crate::utils::constants::{C, Me, Mmu, Mg};
-use crate::utils::physics::{beta, dEdx, ke};
+use crate::geometry::field::Field;
+use crate::geometry::material::Material;
+use crate::utils::constants::{C, HC, LAMBDA_MAX, LAMBDA_MIN, Me, Mmu, Mg, Mn, Mpi, Mp, BREMS_CUT, TAU_MU, TAU_PI};
+use crate::utils::operations::{lorentz_boost, random_direction};
+use crate::utils::physics::{absorption_length, annihilation_mu, attenuation, beta, bound_muon_lifetime, brems_energy_fraction, brems_photon_number, cherenkov_yield, compton_mu, dEdx, energy, fresnel_reflectance, gamma, k_shell_binding, ke, photoelectric_mu, rayleigh_length, sample_annihilation, sample_brems, sample_cherenkov_wavelength, sample_compton, sample_energy_loss, sample_evaporation, sample_michel, sample_rayleigh};
+
+// Muon capture: mean number of neutrons and nuclear temperature of the evaporation spectrum (MeV), taken from oxygen
+// for all nuclei, and gamma lines of oxygen (MeV) with their emission probabilities
+const CAPTURE_NEUTRONS: f64 = 1.0;
+const CAPTURE_TEMPERATURE: f64 = 1.5;
+const CAPTURE_GAMMAS: [(f64, f64); 3] = [(6.322, 0.15), (5.270, 0.10), (0.277, 0.25)];
 
 // Particle state
 #[derive(Debug, Clone)]
@@ -10,21 +21,121 @@ pub struct ParticleState {
     pub r: Vec3,  // position (mm)
     pub p: Vec3,  // momentum (MeV)
     pub m: f64,   // mass (MeV)
+    pub t: f64,   // time (ns)
+    pub tau: f64, // proper time since creation (ns)
     pub alive: bool,
 }
 
 impl ParticleState {
     pub fn new(pos: Vec3, mom: Vec3, mass: f64) -> Self {
-        ParticleState { r: pos, p: mom, m: mass, alive: true }
+        ParticleState { r: pos, p: mom, m: mass, t: 0.0, tau: 0.0, alive: true }
     }
 }
 
 // Particle type
-#[derive(Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
 pub enum ParticleType {
     Electron,
+    Positron,
     Muon,
+    AntiMuon,
     Gamma,
+    Neutron,
+    PionPlus,
+    PionMinus,
+    Proton,
+    OpticalPhoton,
+}
+
+impl ParticleType {
+    // Electric charge (in units of e)
+    pub fn charge(self) -> f64 {
+        match self {
+            ParticleType::Positron | ParticleType::AntiMuon | ParticleType::PionPlus | ParticleType::Proton => 1.0,
+            ParticleType::Electron | ParticleType::Muon | ParticleType::PionMinus                         => -1.0,
+            ParticleType::Gamma | ParticleType::Neutron | ParticleType::OpticalPhoton                     => 0.0,
+        }
+    }
+}
+
+// Process which created a particle
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Process {
+    Primary,
+    Photoelectric,
+    Compton,
+    PairProduction,
+    Bremsstrahlung,
+    Annihilation,
+    Decay,
+    MuonCapture,
+    Cherenkov,
+    Scintillation,
+}
+
+// Simulation mode of the optical photons produced by a process
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PhotonMode {
+    Off,    // not simulated
+    Count,  // only the number of photons is recorded
+    Full,   // photons are emitted as secondary particles
+}
+
+// Scintillation properties of the medium
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scintillation {
+    pub mode: PhotonMode,
+    pub light_yield: f64,    // photons per MeV deposited (unquenched)
+    pub birks: f64,          // Birks' constant kB (mm/MeV)
+    pub tau_fast: f64,       // decay time of the fast component (ns)
+    pub tau_slow: f64,       // decay time of the slow component (ns)
+    pub fast_fraction: f64,  // fraction of the light emitted in the fast component
+    pub wavelength: f64,     // mean emission wavelength (nm)
+    pub wavelength_sigma: f64,  // width of the emission spectrum (nm)
+}
+
+impl Scintillation {
+    // Non-scintillating medium (e.g. water)
+    pub const NONE: Scintillation = Scintillation {
+        mode: PhotonMode::Off,
+        light_yield: 0.0,
+        birks: 0.0,
+        tau_fast: 0.0,
+        tau_slow: 0.0,
+        fast_fraction: 1.0,
+        wavelength: 0.0,
+        wavelength_sigma: 0.0,
+    };
+
+    // Linear alkylbenzene with PPO
+    pub const LAB: Scintillation = Scintillation {
+        mode: PhotonMode::Count,
+        light_yield: 10000.0,
+        birks: 0.1,
+        tau_fast: 4.6,
+        tau_slow: 18.0,
+        fast_fraction: 0.8,
+        wavelength: 390.0,
+        wavelength_sigma: 20.0,
+    };
+
+    // Mean number of photons for an energy deposit, quenched following Birks' law
+    #[allow(non_snake_case)]
+    pub fn mean_photons(&self, edep: f64, dEdx: f64) -> f64 {
+        self.light_yield * edep / (1.0 + self.birks * dEdx)
+    }
+
+    // Delay between the energy deposit and the photon emission (ns)
+    pub fn sample_delay(&self, rng: &mut impl Rng) -> f64 {
+        let tau = if rng.random::<f64>() < self.fast_fraction { self.tau_fast } else { self.tau_slow };
+        -tau * f64::ln(1.0 - rng.random::<f64>())
+    }
+
+    // Emission wavelength (nm), restricted to the simulated range
+    pub fn sample_wavelength(&self, rng: &mut impl Rng) -> f64 {
+        let lambda = Normal::new(self.wavelength, self.wavelength_sigma).unwrap().sample(rng);
+        lambda.clamp(LAMBDA_MIN, LAMBDA_MAX)
+    }
 }
 
 // Particle
@@ -32,41 +143,418 @@ pub enum ParticleType {
 pub struct Particle {
     pub species: ParticleType,
     pub state: ParticleState,
+    pub id: usize,                // track ID, assigned by the world
+    pub parent_id: Option<usize>, // track ID of the particle which created this one (None for primaries)
+    pub creator: Process,
+    pub end_process: Option<Process>,  // process which ended the life of the particle, if any
+    pub mfp_left: Option<f64>,  // mean free paths left before the next discrete interaction
+    pub lifetime_left: Option<f64>,  // proper time left before decaying (ns)
+    pub n_cherenkov: u64,  // number of Cherenkov photons emitted
+    pub n_scintillation: u64,  // number of scintillation photons emitted
+    pub edep: f64,  // energy deposited locally so far (MeV)
 }
 
 impl Particle {
     pub fn new(pos: Vec3, mom: Vec3, part_type: ParticleType) -> Self {
         let mass = match part_type {
-            ParticleType::Electron => Me,
-            ParticleType::Muon     => Mmu,
-            ParticleType::Gamma    => Mg,
+            ParticleType::Electron      => Me,
+            ParticleType::Positron      => Me,
+            ParticleType::Muon          => Mmu,
+            ParticleType::AntiMuon      => Mmu,
+            ParticleType::Gamma         => Mg,
+            ParticleType::Neutron       => Mn,
+            ParticleType::PionPlus      => Mpi,
+            ParticleType::PionMinus     => Mpi,
+            ParticleType::Proton        => Mp,
+            ParticleType::OpticalPhoton => Mg,
         };
         let particle_state = ParticleState::new(pos, mom, mass);
 
-        Particle { species: part_type, state: particle_state }
+        Particle { species: part_type, state: particle_state, id: 0, parent_id: None, creator: Process::Primary, end_process: None, mfp_left: None, lifetime_left: None, n_cherenkov: 0, n_scintillation: 0, edep: 0.0 }
+    }
+
+    // Create a secondary particle at the current position of this particle
+    pub fn secondary(&self, mom: Vec3, part_type: ParticleType, process: Process) -> Particle {
+        let mut particle = Particle::new(self.state.r, mom, part_type);
+        particle.state.t = self.state.t;
+        particle.parent_id = Some(self.id);
+        particle.creator = process;
+        particle
+    }
+
+    // Move the particle for a duration dt (ns): along a straight line, or along its trajectory in the field for charged
+    // particles
+    pub fn propagate(&mut self, dt: f64, material: &Material, field: &Field) {
+        let s = beta(self, material) * C * dt;  // path length: beta[1] * C[mm/ns] * dt[ns]
+        (self.state.r, self.state.p) = field.track(self.state.r, self.state.p, self.state.m, self.species.charge(), s);
+        self.state.t += dt;
+        self.state.tau += gamma(self).map_or(0.0, |g| dt / g);  // no proper time for massless particles
+    }
+
+    // Mean lifetime of unstable particles (ns)
+    pub fn lifetime(&self) -> Option<f64> {
+        match self.species {
+            ParticleType::Muon | ParticleType::AntiMuon       => Some(TAU_MU),
+            ParticleType::PionPlus | ParticleType::PionMinus => Some(TAU_PI),
+            _                                                => None,
+        }
+    }
+
+    // Interact the particle with the material, returning the secondary particles produced. Without straggling, charged
+    // particles lose exactly their mean energy loss in each step.
+    pub fn interact(&mut self, rng: &mut impl Rng, material: &Material, dt: f64, cherenkov: PhotonMode, scintillation: &Scintillation, straggling: bool) -> Vec<Particle> {
+        if let Some(products) = self.decay_in_flight(rng, dt) {
+            return products;
+        }
+        match self.species {
+            ParticleType::Gamma         => self.interact_gamma(rng, material, dt),
+            ParticleType::Neutron       => vec![],  // neutron interactions are not simulated
+            ParticleType::OpticalPhoton => self.interact_optical(rng, material, dt),
+            ParticleType::Positron      => match self.annihilate_in_flight(rng, material, dt) {
+                Some(photons) => photons,
+                None          => self.interact_charged(rng, material, dt, cherenkov, scintillation, straggling),
+            },
+            _                           => self.interact_charged(rng, material, dt, cherenkov, scintillation, straggling),
+        }
+    }
+
+    // End of life of a particle which has come to rest in a material, returning the secondary particles produced
+    #[allow(non_snake_case)]
+    pub fn stop(&mut self, rng: &mut impl Rng, material: &Material) -> Vec<Particle> {
+        self.state.alive = false;
+        self.edep += ke(self);  // the remaining kinetic energy is deposited
+        match self.species {
+            ParticleType::Positron => {
+                // Annihilation at rest into two back-to-back 511keV photons, isotropically
+                self.end_process = Some(Process::Annihilation);
+                let dir = random_direction(rng);
+                vec![self.secondary(dir * Me, ParticleType::Gamma, Process::Annihilation),
+                     self.secondary(-dir * Me, ParticleType::Gamma, Process::Annihilation)]
+            },
+            ParticleType::Muon     => {
+                // Bound in an atom, the muon either decays or is captured by the nucleus, which shortens its
+                // lifetime. Atoms are chosen in proportion to their charge Z (Fermi-Teller law), and muons bound in
+                // hydrogen are transferred to the heavier atoms of the material, if any.
+                let heavy = material.composition.iter().any(|e| e.Z > 1.0);
+                let Z = material.sample_element(rng, |e| if heavy && e.Z <= 1.0 { 0.0 } else { e.Z }).Z;
+                let tau = bound_muon_lifetime(Z);
+                let lifetime = -tau * f64::ln(1.0 - rng.random::<f64>());
+                self.lifetime_left = Some(lifetime);
+                self.wait(lifetime);
+                if rng.random::<f64>() < 1.0 - tau / TAU_MU {
+                    self.capture(rng, Z)
+                } else {
+                    self.decay(rng)
+                }
+            },
+            ParticleType::AntiMuon | ParticleType::PionPlus => {
+                // Decay at rest, after the remaining lifetime
+                let lifetime = self.sample_lifetime(rng);
+                self.wait(lifetime);
+                self.decay(rng)
+            },
+            // Negative pions at rest are absorbed by a nucleus (nuclear fragments are not simulated)
+            _                      => vec![],
+        }
+    }
+
+    // Advance the time of a particle at rest
+    fn wait(&mut self, dt: f64) {
+        self.state.t += dt;
+        self.state.tau += dt;
     }
 
-    pub fn propagate(&mut self, dt: f64) {
+    // Proper time left before the particle decays, sampled from its lifetime if needed
+    fn sample_lifetime(&mut self, rng: &mut impl Rng) -> f64 {
+        let tau = self.lifetime().expect("Stable particles do not decay.");
+        *self.lifetime_left.get_or_insert_with(|| -tau * f64::ln(1.0 - rng.random::<f64>()))
+    }
+
+    // Decay of unstable particles in flight, the proper time elapsed in the step being dt/gamma
+    fn decay_in_flight(&mut self, rng: &mut impl Rng, dt: f64) -> Option<Vec<Particle>> {
+        self.lifetime()?;
+        let lifetime_left = self.sample_lifetime(rng) - dt / gamma(self).unwrap();
+        self.lifetime_left = Some(lifetime_left);
+        if lifetime_left > 0.0 {
+            return None;
+        }
+        Some(self.decay(rng))
+    }
+
+    // Decay the particle, returning its decay products (neutrinos are not simulated)
+    fn decay(&mut self, rng: &mut impl Rng) -> Vec<Particle> {
+        self.state.alive = false;
+        self.end_process = Some(Process::Decay);
+        let beta = self.state.p / energy(self);  // velocity of the particle (c)
+        match self.species {
+            ParticleType::Muon | ParticleType::AntiMuon => {
+                // Michel electron (positron for mu+), emitted isotropically in the muon rest frame
+                let e = sample_michel(rng);
+                let p = random_direction(rng) * (e*e - Me*Me).sqrt();
+                let (_, p_lab) = lorentz_boost(e, p, beta);
+                let species = if self.species == ParticleType::Muon { ParticleType::Electron } else { ParticleType::Positron };
+                vec![self.secondary(p_lab, species, Process::Decay)]
+            },
+            ParticleType::PionPlus | ParticleType::PionMinus => {
+                // Two-body decay into a muon, emitted isotropically in the pion rest frame
+                let p_star = (Mpi*Mpi - Mmu*Mmu) / (2.0 * Mpi);
+                let p = random_direction(rng) * p_star;
+                let (_, p_lab) = lorentz_boost((p_star*p_star + Mmu*Mmu).sqrt(), p, beta);
+                let species = if self.species == ParticleType::PionPlus { ParticleType::AntiMuon } else { ParticleType::Muon };
+                vec![self.secondary(p_lab, species, Process::Decay)]
+            },
+            _                                           => vec![],
+        }
+    }
+
+    // Fresnel reflection or refraction of an optical photon reaching a boundary from a medium of refractive index n1
+    // into one of index n2, with the normal of the boundary pointing into the second medium. Reflected photons are
+    // mirrored about the boundary, refracted ones follow Snell's law. Returns whether the photon crossed the boundary.
+    pub fn cross_boundary(&mut self, rng: &mut impl Rng, normal: Vec3, n1: f64, n2: f64) -> bool {
         let dir = self.state.p.norm();
-        let beta = beta(&self);
-        self.state.r += dir * beta * C * dt;  // dir[1] * beta[1] * C[mm/ns] * dt[ns]
+        let cos_i = dir.dot(normal);
+        if rng.random::<f64>() >= fresnel_reflectance(cos_i, n1, n2) {
+            let eta = n1 / n2;
+            let cos_t = (1.0 - eta*eta * (1.0 - cos_i*cos_i)).sqrt();
+            self.state.p = (dir * eta + normal * (cos_t - eta * cos_i)) * self.state.p.mag();
+            return true;
+        }
+        self.state.p = self.state.p - normal * (2.0 * self.state.p.dot(normal));
+        false
     }
 
-    pub fn interact(&mut self, rng: &mut impl Rng, X0: f64, dt: f64) {
+    // Continuous energy loss and multiple scattering, plus bremsstrahlung for electrons, Cherenkov and scintillation light
+    #[allow(non_snake_case)]
+    fn interact_charged(&mut self, rng: &mut impl Rng, material: &Material, dt: f64, cherenkov: PhotonMode, scintillation: &Scintillation, straggling: bool) -> Vec<Particle> {
+        let mut secondaries = self.emit_cherenkov(rng, material, dt, cherenkov);
+        let X0 = material.radiation_length();
+
         let p = self.state.p.mag();
-        let ke_pre = ke(&self);  // kinetic energy before step
+        let ke_pre = ke(self);  // kinetic energy before step
 
-        let beta = beta(&self);
+        let beta = beta(self, material);
         let dx = beta * C * dt;  // step size (mm)
-        let dEdx = dEdx(&self);
+        let dEdx_collision = dEdx(self, material);
+        let mut dEdx = dEdx_collision;
         let theta0 = (13.6 / (beta * p)) * (dx/X0).sqrt() * (1.0 + 0.038 * f64::ln(dx/X0));
 
-        // Subtract energy lost in step, clamp to 0 if negative
-        let ke_post = f64::max(ke_pre - (dEdx * dx), 0.0);
+        // Bremsstrahlung: photons below the cut are treated as a continuous energy loss, the others are emitted
+        let mut n_photons = 0;
+        let (mut y_cut, mut y_max, e) = (0.0, 0.0, energy(self));
+        if matches!(self.species, ParticleType::Electron | ParticleType::Positron) {
+            y_cut = BREMS_CUT / e;
+            y_max = ke_pre / e;
+            dEdx += e / X0 * brems_energy_fraction(0.0, f64::min(y_cut, y_max));
+            if y_cut < y_max {
+                let n_mean = dx / X0 * brems_photon_number(y_cut, y_max);
+                n_photons = Poisson::new(n_mean).unwrap().sample(rng) as usize;
+            }
+        }
+
+        // Subtract energy lost in step, clamp to 0 if negative. The collision losses fluctuate around their mean.
+        let mut collision_loss = dEdx_collision * dx;
+        if straggling {
+            collision_loss = sample_energy_loss(rng, self, material, collision_loss, dx);
+        }
+        let mut ke_post = f64::max(ke_pre - collision_loss - (dEdx - dEdx_collision) * dx, 0.0);
+
+        // The collision losses are deposited locally, and turned into scintillation light
+        let edep = f64::min(collision_loss, ke_pre);
+        self.edep += edep;
+        secondaries.extend(self.emit_scintillation(rng, material, dt, edep, dEdx_collision, scintillation));
+
+        // Emit the bremsstrahlung photons along the electron direction, as long as there is enough energy left
+        let dir = self.state.p.norm();
+        let mut photons = vec![];
+        for _ in 0..n_photons {
+            let k = sample_brems(rng, y_cut, y_max) * e;
+            if k <= ke_post {
+                ke_post -= k;
+                photons.push(self.secondary(dir * k, ParticleType::Gamma, Process::Bremsstrahlung));
+            }
+        }
+
         // Resize momentum vector with new momentum
         self.state.p = self.state.p.norm() * (ke_post * (ke_post + 2.0*self.state.m)).sqrt();
         // Deflect momentum vector
         self.state.p.deflect(rng, theta0);
+
+        secondaries.extend(photons);
+        secondaries
+    }
+
+    // Cherenkov light emitted along the step (which has already been travelled) with the Frank-Tamm yield,
+    // at the Cherenkov angle around the momentum
+    fn emit_cherenkov(&mut self, rng: &mut impl Rng, material: &Material, dt: f64, mode: PhotonMode) -> Vec<Particle> {
+        let beta = beta(self, material);
+        let n_mean = cherenkov_yield(beta, material.n) * beta * C * dt;
+        if mode == PhotonMode::Off || n_mean <= 0.0 {
+            return vec![];
+        }
+        let n = Poisson::new(n_mean).unwrap().sample(rng) as u64;
+        self.n_cherenkov += n;
+        if mode == PhotonMode::Count {
+            return vec![];
+        }
+
+        let dir = self.state.p.norm();
+        let theta = f64::acos(1.0 / (beta * material.n));
+        (0..n).map(|_| {
+            let back = 1.0 - rng.random::<f64>();  // fraction of the step between emission point and current position
+            let lambda = sample_cherenkov_wavelength(rng);
+            let phi = 2.0 * std::f64::consts::PI * rng.random::<f64>();
+            let mut photon = self.secondary(dir.rotate(theta, phi) * (HC / lambda), ParticleType::OpticalPhoton, Process::Cherenkov);
+            photon.state.r = photon.state.r - dir * (beta * C * dt * back);
+            photon.state.t -= dt * back;
+            photon
+        }).collect()
+    }
+
+    // Scintillation light from an energy deposit along the step (which has already been travelled), emitted
+    // isotropically after a delay given by the fast and slow components of the scintillator
+    #[allow(non_snake_case)]
+    fn emit_scintillation(&mut self, rng: &mut impl Rng, material: &Material, dt: f64, edep: f64, dEdx: f64, scintillation: &Scintillation) -> Vec<Particle> {
+        let n_mean = scintillation.mean_photons(edep, dEdx);
+        if scintillation.mode == PhotonMode::Off || n_mean <= 0.0 {
+            return vec![];
+        }
+        let n = Poisson::new(n_mean).unwrap().sample(rng) as u64;
+        self.n_scintillation += n;
+        if scintillation.mode == PhotonMode::Count {
+            return vec![];
+        }
+
+        let (dir, dx) = (self.state.p.norm(), beta(self, material) * C * dt);
+        (0..n).map(|_| {
+            let back = 1.0 - rng.random::<f64>();  // fraction of the step between deposit point and current position
+            let lambda = scintillation.sample_wavelength(rng);
+            let mut photon = self.secondary(random_direction(rng) * (HC / lambda), ParticleType::OpticalPhoton, Process::Scintillation);
+            photon.state.r = photon.state.r - dir * (dx * back);
+            photon.state.t += scintillation.sample_delay(rng) - dt * back;
+            photon
+        }).collect()
+    }
+
+    // Nuclear capture of a negative muon at rest by a nucleus of atomic number Z (e.g. mu- + 16O -> nu + 16N*),
+    // returning the products of the de-excitation of the nucleus: a Poisson number of evaporation neutrons, and the
+    // gamma lines of oxygen (not simulated for other nuclei). The neutron of the capture on a free proton
+    // (mu- + p -> n + nu), which only happens in pure hydrogen, is not simulated.
+    #[allow(non_snake_case)]
+    fn capture(&mut self, rng: &mut impl Rng, Z: f64) -> Vec<Particle> {
+        self.state.alive = false;
+        self.end_process = Some(Process::MuonCapture);
+        let mut products = vec![];
+        if Z <= 1.0 {
+            return products;
+        }
+
+        let n_neutrons = Poisson::new(CAPTURE_NEUTRONS).unwrap().sample(rng) as usize;
+        for _ in 0..n_neutrons {
+            let ke = sample_evaporation(rng, CAPTURE_TEMPERATURE);
+            let p = random_direction(rng) * (ke * (ke + 2.0*Mn)).sqrt();
+            products.push(self.secondary(p, ParticleType::Neutron, Process::MuonCapture));
+        }
+        let gammas = if Z == 8.0 { CAPTURE_GAMMAS.as_slice() } else { &[] };
+        for &(e, probability) in gammas {
+            if rng.random::<f64>() < probability {
+                products.push(self.secondary(random_direction(rng) * e, ParticleType::Gamma, Process::MuonCapture));
+            }
+        }
+        products
+    }
+
+    // Two-photon annihilation of a positron in flight, sampled from the Heitler cross section
+    fn annihilate_in_flight(&mut self, rng: &mut impl Rng, material: &Material, dt: f64) -> Option<Vec<Particle>> {
+        let dx = beta(self, material) * C * dt;
+        if rng.random::<f64>() >= 1.0 - f64::exp(-annihilation_mu(self, material) * dx) {
+            return None;
+        }
+        self.state.alive = false;
+        self.end_process = Some(Process::Annihilation);
+
+        // The first photon is sampled, the second one takes the remaining energy and momentum
+        let ke = ke(self);
+        let (eps, cos_theta) = sample_annihilation(rng, ke);
+        let phi = 2.0 * std::f64::consts::PI * rng.random::<f64>();
+        let k1 = eps * (ke + 2.0*Me);
+        let p1 = self.state.p.norm().rotate(cos_theta.acos(), phi) * k1;
+        Some(vec![self.secondary(p1, ParticleType::Gamma, Process::Annihilation),
+                  self.secondary(self.state.p - p1, ParticleType::Gamma, Process::Annihilation)])
+    }
+
+    // Discrete photon interactions: the interaction point is sampled from the total attenuation
+    // coefficient, then the process is chosen from the partial coefficients
+    fn interact_gamma(&mut self, rng: &mut impl Rng, material: &Material, dt: f64) -> Vec<Particle> {
+        let e = energy(self);
+        let mu = attenuation(self, material);
+        let dx = C * dt;
+
+        // Sample the number of mean free paths to the next interaction if needed
+        let mfp_left = self.mfp_left.unwrap_or_else(|| -f64::ln(1.0 - rng.random::<f64>())) - mu * dx;
+        if mfp_left > 0.0 {
+            self.mfp_left = Some(mfp_left);
+            return vec![];
+        }
+        self.mfp_left = None;
+
+        let dir = self.state.p.norm();
+        let r = rng.random::<f64>() * mu;
+        if r < photoelectric_mu(e, material) {
+            // Photoelectric absorption on an atom chosen from its cross section (going as Z^4.5): the electron is
+            // ejected along the photon direction, and the K-shell binding energy is deposited locally (the binding of
+            // the outer shells is neglected below the K edge)
+            self.state.alive = false;
+            self.end_process = Some(Process::Photoelectric);
+            let binding = k_shell_binding(material.sample_element(rng, |element| element.Z.powf(4.5)).Z);
+            let ke_e = if e > binding { e - binding } else { e };
+            self.edep += e - ke_e;
+            let p_e = (ke_e * (ke_e + 2.0*Me)).sqrt();
+            vec![self.secondary(dir * p_e, ParticleType::Electron, Process::Photoelectric)]
+        } else if r < photoelectric_mu(e, material) + compton_mu(e, material) {
+            // Compton scattering: the electron takes the momentum lost by the photon
+            let (eps, cos_theta) = sample_compton(rng, e);
+            let phi = 2.0 * std::f64::consts::PI * rng.random::<f64>();
+            let p_pre = self.state.p;
+            self.state.p = self.state.p.rotate(cos_theta.acos(), phi) * eps;
+            vec![self.secondary(p_pre - self.state.p, ParticleType::Electron, Process::Compton)]
+        } else {
+            // Pair production: the available kinetic energy is shared uniformly,
+            // and the pair is emitted along the photon direction
+            self.state.alive = false;
+            self.end_process = Some(Process::PairProduction);
+            let ke_total = e - 2.0*Me;
+            let ke_e = ke_total * rng.random::<f64>();
+            let ke_p = ke_total - ke_e;
+            vec![self.secondary(dir * (ke_e * (ke_e + 2.0*Me)).sqrt(), ParticleType::Electron, Process::PairProduction),
+                 self.secondary(dir * (ke_p * (ke_p + 2.0*Me)).sqrt(), ParticleType::Positron, Process::PairProduction)]
+        }
+    }
+
+    // Optical photon absorption and Rayleigh scattering, sampled like the gamma interactions
+    fn interact_optical(&mut self, rng: &mut impl Rng, material: &Material, dt: f64) -> Vec<Particle> {
+        // Opaque materials absorb the photons straight away
+        if material.transparency == 0.0 {
+            self.state.alive = false;
+            return vec![];
+        }
+        let lambda = HC / self.state.p.mag();
+        let (mu_abs, mu_ray) = (1.0 / absorption_length(lambda, material), 1.0 / rayleigh_length(lambda, material));
+        let dx = beta(self, material) * C * dt;
+
+        let mfp_left = self.mfp_left.unwrap_or_else(|| -f64::ln(1.0 - rng.random::<f64>())) - (mu_abs + mu_ray) * dx;
+        if mfp_left > 0.0 {
+            self.mfp_left = Some(mfp_left);
+            return vec![];
+        }
+        self.mfp_left = None;
+
+        if rng.random::<f64>() * (mu_abs + mu_ray) < mu_abs {
+            self.state.alive = false;
+        } else {
+            let phi = 2.0 * std::f64::consts::PI * rng.random::<f64>();
+            self.state.p = self.state.p.rotate(sample_rayleigh(rng).acos(), phi);
+        }
+        vec![]
     }
 }
 
@@ -77,6 +565,27 @@ mod tests {
     use super::*;
     use crate::assert_vec3_eq;
     use approx::assert_relative_eq;
+    use rand::{rngs::StdRng, RngCore, SeedableRng};
+    use crate::utils::constants::{KAPPA, LAMBDA_MAX, LAMBDA_MIN};
+    use std::f64::consts::PI;
+    use crate::geometry::material::{AIR, LAB, WATER};
+
+    // Random number generator always returning the same number in [0, 1), to force the outcome of random choices
+    struct FixedRng(f64);
+
+    impl RngCore for FixedRng {
+        fn next_u32(&mut self) -> u32 {
+            (self.0 * 2f64.powi(32)) as u32
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            (self.0 * 2f64.powi(64)) as u64
+        }
+
+        fn fill_bytes(&mut self, dst: &mut [u8]) {
+            dst.fill((self.0 * 256.0) as u8);
+        }
+    }
 
     #[test]
     fn test_particlestate_creation() {
@@ -89,11 +598,11 @@ mod tests {
         assert_vec3_eq!(ps1.r, v1);
         assert_vec3_eq!(ps1.p, v2);
         assert_relative_eq!(ps1.m, m1);
-        assert_eq!(ps1.alive, true);
+        assert!(ps1.alive);
         assert_vec3_eq!(ps2.r, v2);
         assert_vec3_eq!(ps2.p, v1);
         assert_relative_eq!(ps2.m, m2);
-        assert_eq!(ps2.alive, true);
+        assert!(ps2.alive);
     }
 
     #[test]
@@ -101,6 +610,13 @@ mod tests {
         let _pt1 = ParticleType::Electron;
         let _pt2 = ParticleType::Muon;
         let _pt3 = ParticleType::Gamma;
+        let _pt4 = ParticleType::Positron;
+        let _pt5 = ParticleType::Neutron;
+        let _pt6 = ParticleType::AntiMuon;
+        let _pt7 = ParticleType::PionPlus;
+        let _pt8 = ParticleType::PionMinus;
+        let _pt9 = ParticleType::Proton;
+        let _pt10 = ParticleType::OpticalPhoton;
     }
 
     #[test]
@@ -111,15 +627,15 @@ mod tests {
         assert_vec3_eq!(electron.state.r, Vec3(0.0, 0.0, 0.0));
         assert_vec3_eq!(electron.state.p, Vec3(1.0, 0.0, 0.0));
         assert_relative_eq!(electron.state.m, 0.511);
-        assert_eq!(electron.state.alive, true);
+        assert!(electron.state.alive);
         assert_vec3_eq!(muon.state.r, Vec3(5.0, -2.0, 10.0));
         assert_vec3_eq!(muon.state.p, Vec3(2.0, 3.0, -4.0));
         assert_relative_eq!(muon.state.m, 105.66);
-        assert_eq!(muon.state.alive, true);
+        assert!(muon.state.alive);
         assert_vec3_eq!(gamma.state.r, Vec3(-1.2, 7.6, 6.7));
         assert_vec3_eq!(gamma.state.p, Vec3(-9.8, -2.5, -1.1));
         assert_relative_eq!(gamma.state.m, 0.0);
-        assert_eq!(gamma.state.alive, true);
+        assert!(gamma.state.alive);
     }
 
     #[test]
@@ -128,17 +644,354 @@ mod tests {
         let mut muon1 = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 1.0, 0.0), ParticleType::Muon);
         let mut gamma1 = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 1.0), ParticleType::Gamma);
 
-        electron1.propagate(1.0);
+        electron1.propagate(1.0, &WATER, &Field::default());
         assert_vec3_eq!(electron1.state.r, Vec3(266.9576214377587, 0.0, 0.0));
-        electron1.propagate(0.1);
+        electron1.propagate(0.1, &WATER, &Field::default());
         assert_vec3_eq!(electron1.state.r, Vec3(266.9576214377587+26.69576214377587, 0.0, 0.0));
-        muon1.propagate(1.0);
+        muon1.propagate(1.0, &WATER, &Field::default());
         assert_vec3_eq!(muon1.state.r, Vec3(0.0, 2.837204544727953, 0.0));
-        muon1.propagate(0.1);
+        muon1.propagate(0.1, &WATER, &Field::default());
         assert_vec3_eq!(muon1.state.r, Vec3(0.0, 2.837204544727953+0.2837204544727953, 0.0));
-        gamma1.propagate(1.0);
+        gamma1.propagate(1.0, &WATER, &Field::default());
         assert_vec3_eq!(gamma1.state.r, Vec3(0.0, 0.0, 299.792458));
-        gamma1.propagate(0.1);
+        gamma1.propagate(0.1, &WATER, &Field::default());
         assert_vec3_eq!(gamma1.state.r, Vec3(0.0, 0.0, 299.792458+29.9792458));
+
+        // Time and proper time
+        assert_relative_eq!(electron1.state.t, 1.1);
+        assert_relative_eq!(electron1.state.tau, 1.1 / gamma(&electron1).unwrap());
+        assert_relative_eq!(muon1.state.tau, 1.1 / gamma(&muon1).unwrap());
+        assert_relative_eq!(gamma1.state.t, 1.1);
+        assert_relative_eq!(gamma1.state.tau, 0.0);
+    }
+
+    #[test]
+    fn test_particle_helix() {
+        let field = &Field::Uniform(Vec3(0.0, 0.0, 1.0));
+        let radius = 100.0 / KAPPA;
+
+        // Half a turn: positive particles turn clockwise around the field, negative ones anticlockwise
+        let mut positron = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(100.0, 0.0, 0.0), ParticleType::Positron);
+        let dt = PI * radius / (beta(&positron, &WATER) * C);
+        positron.propagate(dt, &WATER, field);
+        assert_vec3_eq!(positron.state.r, Vec3(0.0, -2.0 * radius, 0.0), 1e-9);
+        assert_vec3_eq!(positron.state.p, Vec3(-100.0, 0.0, 0.0), 1e-9);
+        let mut electron = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(100.0, 0.0, 0.0), ParticleType::Electron);
+        electron.propagate(dt / 2.0, &WATER, field);
+        assert_vec3_eq!(electron.state.r, Vec3(radius, radius, 0.0), 1e-9);
+        electron.propagate(dt / 2.0, &WATER, field);
+        assert_vec3_eq!(electron.state.r, Vec3(0.0, 2.0 * radius, 0.0), 1e-9);
+
+        // Full turn of a helix: the particle moves along the field by the pitch, with the same momentum
+        let mut muon = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 60.0, 80.0), ParticleType::Muon);
+        let length = 2.0 * PI * radius;
+        muon.propagate(length / (beta(&muon, &WATER) * C), &WATER, field);
+        assert_vec3_eq!(muon.state.r, Vec3(0.0, 0.0, 0.8 * length), 1e-9);
+        assert_vec3_eq!(muon.state.p, Vec3(0.0, 60.0, 80.0), 1e-9);
+
+        // Neutral particles, and particles along the field, go straight
+        let mut neutron = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(100.0, 0.0, 0.0), ParticleType::Neutron);
+        neutron.propagate(1.0, &WATER, field);
+        assert_vec3_eq!(neutron.state.r.cross(Vec3(1.0, 0.0, 0.0)), Vec3(0.0, 0.0, 0.0), 1e-9);
+        let mut proton = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 100.0), ParticleType::Proton);
+        proton.propagate(1.0, &WATER, field);
+        assert_vec3_eq!(proton.state.p, Vec3(0.0, 0.0, 100.0), 1e-9);
+        assert_eq!([ParticleType::Proton, ParticleType::PionMinus, ParticleType::OpticalPhoton].map(ParticleType::charge), [1.0, -1.0, 0.0]);
+    }
+
+    #[test]
+    fn test_particle_interact_brems() {
+        let mut rng = StdRng::seed_from_u64(8);
+        let mut electron = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 1000.0), ParticleType::Electron);
+        let mut muon = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 1000.0), ParticleType::Muon);
+        let ke_pre = ke(&electron);
+
+        // A 1 GeV electron radiates ~10 photons over a 1 X0 step
+        let photons = electron.interact(&mut rng, &WATER, 360.8 / C, PhotonMode::Count, &Scintillation::NONE, false);
+        assert!(!photons.is_empty());
+        let ke_photons: f64 = photons.iter().map(|photon| photon.state.p.mag()).sum();
+        assert!(ke_photons <= ke_pre - ke(&electron));
+        for photon in &photons {
+            assert_eq!(photon.species, ParticleType::Gamma);
+            assert_eq!(photon.creator, Process::Bremsstrahlung);
+            assert!(photon.state.p.mag() >= BREMS_CUT);
+        }
+        // Muons do not radiate
+        assert!(muon.interact(&mut rng, &WATER, 360.8 / C, PhotonMode::Count, &Scintillation::NONE, false).is_empty());
+    }
+
+    #[test]
+    fn test_particle_positron() {
+        let mut rng = StdRng::seed_from_u64(21);
+        let mut positron1 = Particle::new(Vec3(1.0, 2.0, 3.0), Vec3(0.0, 0.0, 0.0), ParticleType::Positron);
+        let mut positron2 = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(2.0, 0.0, 0.0), ParticleType::Positron);
+        assert_relative_eq!(positron1.state.m, 0.511);
+
+        // Annihilation at rest
+        let photons = positron1.stop(&mut rng, &WATER);
+        assert!(!positron1.state.alive);
+        assert_eq!(photons.len(), 2);
+        assert_relative_eq!(photons[0].state.p.mag(), Me);
+        assert_vec3_eq!(photons[0].state.p, -photons[1].state.p);
+        assert_vec3_eq!(photons[0].state.r, Vec3(1.0, 2.0, 3.0));
+
+        // Annihilation in flight over a very long step conserves energy and momentum
+        let e_pre = energy(&positron2);
+        let photons = positron2.interact(&mut rng, &WATER, 1e4, PhotonMode::Count, &Scintillation::NONE, false);
+        assert!(!positron2.state.alive);
+        assert_eq!(photons.len(), 2);
+        assert!(photons.iter().all(|photon| photon.creator == Process::Annihilation));
+        assert_relative_eq!(photons[0].state.p.mag() + photons[1].state.p.mag(), e_pre + Me, max_relative = 1e-9);
+        assert_vec3_eq!(photons[0].state.p + photons[1].state.p, Vec3(2.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_particle_muon_decay() {
+        let mut rng = StdRng::seed_from_u64(16);
+        let mut muon1 = Particle::new(Vec3(1.0, 2.0, 3.0), Vec3(0.0, 0.0, 0.0), ParticleType::Muon);
+        let mut muon2 = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 1000.0), ParticleType::Muon);
+        let mut electron = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 1.0), ParticleType::Electron);
+
+        // Decay at rest: the Michel electron is delayed by the muon lifetime. Random numbers fixed at 0.75 rule out
+        // the nuclear capture (probability 18%), and are accepted by the sampling of the Michel spectrum.
+        let products = muon1.stop(&mut FixedRng(0.75), &WATER);
+        assert!(!muon1.state.alive);
+        assert_eq!(muon1.end_process, Some(Process::Decay));
+        assert_eq!(products.len(), 1);
+        assert_eq!(products[0].species, ParticleType::Electron);
+        assert_eq!(products[0].creator, Process::Decay);
+        assert!(products[0].state.t > 0.0);
+        assert_relative_eq!(products[0].state.t, muon1.state.t);
+        assert_relative_eq!(products[0].state.t, muon1.lifetime_left.unwrap());
+        assert_relative_eq!(muon1.state.tau, muon1.state.t);  // at rest, proper time is the time
+        assert!(energy(&products[0]) <= 52.83);
+
+        // Decay in flight: the lifetime is dilated
+        muon2.interact(&mut rng, &WATER, 1.0, PhotonMode::Count, &Scintillation::NONE, false);
+        let lifetime_left = muon2.lifetime_left.unwrap();
+        let gamma = gamma(&muon2).unwrap();
+        muon2.interact(&mut rng, &WATER, 1.0, PhotonMode::Count, &Scintillation::NONE, false);
+        assert_relative_eq!(lifetime_left - muon2.lifetime_left.unwrap(), 1.0 / gamma, max_relative = 1e-9);
+        let products = muon2.interact(&mut rng, &WATER, 1e6, PhotonMode::Count, &Scintillation::NONE, false);
+        assert!(!muon2.state.alive);
+        assert_eq!(products.len(), 1);
+        assert!(products[0].state.p.2 > 0.0);
+
+        // Stable particles never decay
+        electron.interact(&mut rng, &WATER, 1e-3, PhotonMode::Count, &Scintillation::NONE, false);
+        assert!(electron.lifetime().is_none() && electron.lifetime_left.is_none());
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn test_particle_muon_capture() {
+        let mut rng = StdRng::seed_from_u64(17);
+        let muon = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 0.0), ParticleType::Muon);
+        let n = 10000;
+
+        // Muons bound in oxygen (water) or carbon (LAB, hydrogen transferring its muons to carbon): the capture rate
+        // increases with Z, which shortens the lifetime, and gamma lines are only produced by oxygen
+        for (material, Z, captured) in [(&WATER, 8.0, 0.183), (&LAB, 6.0, 0.078)] {
+            let (mut n_captures, mut n_neutrons, mut n_gammas, mut lifetime) = (0, 0, 0, 0.0);
+            for _ in 0..n {
+                let mut stopped = muon.clone();
+                let products = stopped.stop(&mut rng, material);
+                lifetime += stopped.state.t / n as f64;
+                if stopped.end_process == Some(Process::MuonCapture) {
+                    assert!(products.iter().all(|product| product.creator == Process::MuonCapture));
+                    n_captures += 1;
+                    n_neutrons += products.iter().filter(|product| product.species == ParticleType::Neutron).count();
+                    n_gammas += products.iter().filter(|product| product.species == ParticleType::Gamma).count();
+                } else {
+                    assert_eq!(stopped.end_process, Some(Process::Decay));
+                    assert_eq!(products.len(), 1);
+                    assert_eq!(products[0].creator, Process::Decay);
+                }
+            }
+            assert_relative_eq!(n_captures as f64 / n as f64, captured, max_relative = 0.1);
+            assert_relative_eq!(n_neutrons as f64 / n_captures as f64, CAPTURE_NEUTRONS, max_relative = 0.1);
+            assert_eq!(n_gammas > 0, Z == 8.0);
+            assert_relative_eq!(lifetime, bound_muon_lifetime(Z), max_relative = 0.05);
+        }
+    }
+
+    #[test]
+    fn test_particle_hadrons() {
+        let mut rng = StdRng::seed_from_u64(19);
+        let mut pion1 = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 0.0), ParticleType::PionPlus);
+        let mut pion2 = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 0.0), ParticleType::PionMinus);
+        let mut pion3 = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 500.0), ParticleType::PionMinus);
+        let mut proton = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 0.0), ParticleType::Proton);
+        let mut antimuon = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 0.0), ParticleType::AntiMuon);
+        assert_relative_eq!(pion1.state.m, 139.570);
+        assert_relative_eq!(proton.state.m, 938.272);
+        assert_relative_eq!(antimuon.state.m, 105.66);
+
+        // pi+ at rest decays into a 4.1 MeV mu+
+        let products = pion1.stop(&mut rng, &WATER);
+        assert_eq!(products.len(), 1);
+        assert_eq!(products[0].species, ParticleType::AntiMuon);
+        assert_relative_eq!(ke(&products[0]), 4.12, max_relative = 0.01);
+        assert!(products[0].state.t > 0.0);
+
+        // pi- at rest is absorbed, pi- in flight decays into a mu-
+        assert!(pion2.stop(&mut rng, &WATER).is_empty());
+        let products = pion3.interact(&mut rng, &WATER, 1e4, PhotonMode::Count, &Scintillation::NONE, false);
+        assert_eq!(products.len(), 1);
+        assert_eq!(products[0].species, ParticleType::Muon);
+        assert!(products[0].state.p.2 > 0.0);
+
+        // mu+ decays into a positron, protons are stable
+        let products = antimuon.stop(&mut rng, &WATER);
+        assert_eq!(products[0].species, ParticleType::Positron);
+        assert!(proton.stop(&mut rng, &WATER).is_empty());
+    }
+
+    #[test]
+    fn test_particle_cherenkov() {
+        let mut rng = StdRng::seed_from_u64(23);
+        let electron = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 10.0), ParticleType::Electron);
+        let dt = 1.0 / C;  // ~1mm step
+
+        // Below threshold (KE < 0.26 MeV)
+        let mut slow = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 0.3), ParticleType::Electron);
+        slow.interact(&mut rng, &WATER, dt, PhotonMode::Count, &Scintillation::NONE, false);
+        assert_eq!(slow.n_cherenkov, 0);
+
+        // Count only: photons are counted but not emitted
+        let mut counted = electron.clone();
+        let secondaries = counted.interact(&mut rng, &WATER, dt, PhotonMode::Count, &Scintillation::NONE, false);
+        assert!(secondaries.iter().all(|secondary| secondary.species != ParticleType::OpticalPhoton));
+        assert!(counted.n_cherenkov > 10);
+
+        // Full emission: photons at the Cherenkov angle, with the given wavelength range
+        let mut emitting = electron.clone();
+        let secondaries = emitting.interact(&mut rng, &WATER, dt, PhotonMode::Full, &Scintillation::NONE, false);
+        let photons = secondaries.iter().filter(|secondary| secondary.creator == Process::Cherenkov).collect::<Vec<_>>();
+        assert_eq!(photons.len() as u64, emitting.n_cherenkov);
+        let cos_theta = 1.0 / (beta(&electron, &WATER) * WATER.n);
+        for photon in photons {
+            assert_eq!(photon.species, ParticleType::OpticalPhoton);
+            assert_relative_eq!(photon.state.p.norm().2, cos_theta, max_relative = 1e-9);
+            assert!((HC/LAMBDA_MAX..=HC/LAMBDA_MIN).contains(&photon.state.p.mag()));
+            assert!((-1.0..=0.0).contains(&photon.state.r.2));
+            assert!(photon.state.t <= 0.0);
+        }
+
+        // No Cherenkov light when switched off
+        let mut off = electron.clone();
+        off.interact(&mut rng, &WATER, dt, PhotonMode::Off, &Scintillation::NONE, false);
+        assert_eq!(off.n_cherenkov, 0);
+    }
+
+    #[test]
+    fn test_particle_scintillation() {
+        let mut rng = StdRng::seed_from_u64(31);
+        let dt = 1.0 / C;  // ~1mm step for relativistic particles
+        let lab = Scintillation::LAB;
+
+        // Birks' quenching: the light per MeV is reduced at high dE/dx
+        assert_relative_eq!(lab.mean_photons(1.0, 0.0), 10000.0);
+        assert_relative_eq!(lab.mean_photons(1.0, 10.0), 5000.0);
+
+        // Count only: the number of photons follows the (quenched) energy deposit
+        let muon = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 1000.0), ParticleType::Muon);
+        let dx = beta(&muon, &WATER) * C * dt;
+        let mut counted = muon.clone();
+        let secondaries = counted.interact(&mut rng, &WATER, dt, PhotonMode::Off, &lab, false);
+        assert!(secondaries.is_empty());
+        let n_expected = lab.mean_photons(dEdx(&muon, &WATER) * dx, dEdx(&muon, &WATER));
+        assert_relative_eq!(counted.n_scintillation as f64, n_expected, max_relative = 0.2);
+
+        // Full emission: isotropic photons, delayed by the scintillation decay time
+        let mut emitting = muon.clone();
+        let photons = emitting.interact(&mut rng, &WATER, dt, PhotonMode::Off, &Scintillation { mode: PhotonMode::Full, ..lab }, false);
+        assert_eq!(photons.len() as u64, emitting.n_scintillation);
+        let mean_dir = photons.iter().fold(Vec3(0.0, 0.0, 0.0), |sum, photon| sum + photon.state.p.norm()) * (1.0 / photons.len() as f64);
+        assert!(mean_dir.mag() < 0.1);
+        let mean_delay = photons.iter().map(|photon| photon.state.t).sum::<f64>() / photons.len() as f64;
+        assert_relative_eq!(mean_delay, 0.8 * 4.6 + 0.2 * 18.0, max_relative = 0.1);
+        for photon in photons {
+            assert_eq!(photon.species, ParticleType::OpticalPhoton);
+            assert_eq!(photon.creator, Process::Scintillation);
+            assert!(photon.state.t > -dt);
+            assert!((HC/LAMBDA_MAX..=HC/LAMBDA_MIN).contains(&photon.state.p.mag()));
+        }
+
+        // No light from water
+        let mut water = muon.clone();
+        water.interact(&mut rng, &WATER, dt, PhotonMode::Off, &Scintillation::NONE, false);
+        assert_eq!(water.n_scintillation, 0);
+    }
+
+    #[test]
+    fn test_particle_optical_photon() {
+        let mut rng = StdRng::seed_from_u64(41);
+        let p = HC / 420.0;
+
+        // Transport: photons are absorbed or scattered after ~1/(mu_abs + mu_ray) (~69m at 420nm)
+        let mut absorbed = 0;
+        let mut distances = vec![];
+        for _ in 0..1000 {
+            let mut photon = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, p), ParticleType::OpticalPhoton);
+            let mut steps = 0;
+            while photon.state.alive && photon.state.p.norm().2 > 1.0 - 1e-12 {
+                photon.interact(&mut rng, &WATER, 1000.0 / C, PhotonMode::Count, &Scintillation::NONE, false);
+                steps += 1;
+            }
+            absorbed += !photon.state.alive as usize;
+            distances.push(steps as f64 * 1000.0 / WATER.n);
+            assert_relative_eq!(photon.state.p.mag(), p, max_relative = 1e-9);
+        }
+        let mean_distance = distances.iter().sum::<f64>() / 1000.0;
+        assert_relative_eq!(mean_distance, 1.0 / (1.0/2.2e5 + 1.0/1.0e5 * (400.0f64/420.0).powi(4)), max_relative = 0.1);
+        assert!(absorbed > 200 && absorbed < 400);
+
+        // Boundary: photons at normal incidence leave the volume, photons beyond the critical angle are reflected
+        let up = Vec3(0.0, 0.0, 1.0);
+        let normal = Particle::new(Vec3(0.0, 0.0, 50.0), Vec3(0.0, 0.0, p), ParticleType::OpticalPhoton);
+        let mut left = 0;
+        for _ in 0..1000 {
+            left += normal.clone().cross_boundary(&mut rng, up, WATER.n, AIR.n) as usize;
+        }
+        assert_relative_eq!(left as f64 / 1000.0, 1.0 - (0.3297f64 / 2.3303).powi(2), max_relative = 0.02);
+        let mut grazing = Particle::new(Vec3(10.0, 0.0, 50.0), Vec3(2.0*p, 0.0, p), ParticleType::OpticalPhoton);
+        assert!(!grazing.cross_boundary(&mut rng, up, WATER.n, AIR.n));
+        assert_vec3_eq!(grazing.state.r, Vec3(10.0, 0.0, 50.0));
+        assert_vec3_eq!(grazing.state.p, Vec3(2.0*p, 0.0, -p));
+        // Refraction into a denser medium (Snell's law)
+        let oblique = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.5, 0.0, f64::sqrt(0.75)) * p, ParticleType::OpticalPhoton);
+        let mut refracted = oblique.clone();
+        while !refracted.cross_boundary(&mut rng, up, 1.0, 1.5) {
+            refracted = oblique.clone();
+        }
+        assert_relative_eq!(refracted.state.p.mag(), p, max_relative = 1e-12);
+        assert_relative_eq!(refracted.state.p.0 / p, 0.5 / 1.5, max_relative = 1e-12);
+        assert!(refracted.state.p.2 > 0.0);
+    }
+
+    #[test]
+    fn test_particle_interact_gamma() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut gamma1 = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(2.0, 0.0, 0.0), ParticleType::Gamma);
+        let mut gamma2 = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 0.02), ParticleType::Gamma);
+
+        // Short step: the photon is (almost surely) unaffected
+        gamma1.interact(&mut rng, &WATER, 1e-6, PhotonMode::Count, &Scintillation::NONE, false);
+        assert_vec3_eq!(gamma1.state.p, Vec3(2.0, 0.0, 0.0));
+        assert!(gamma1.mfp_left.unwrap() > 0.0);
+
+        // Very long step: the photon interacts, and can only lose energy
+        gamma1.interact(&mut rng, &WATER, 1e6, PhotonMode::Count, &Scintillation::NONE, false);
+        assert!(gamma1.mfp_left.is_none());
+        assert!(!gamma1.state.alive || gamma1.state.p.mag() < 2.0);
+
+        // Low energy photons are mostly absorbed (photoelectric effect)
+        let absorbed = (0..100).filter(|_| {
+            gamma2.state.alive = true;
+            gamma2.interact(&mut rng, &WATER, 1e6, PhotonMode::Count, &Scintillation::NONE, false);
+            !gamma2.state.alive
+        }).count();
+        assert!(absorbed > 70);
     }
 }
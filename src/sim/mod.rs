@@ -1 +1,3 @@
 pub mod world;
+pub mod stepping;
+pub mod trajectory;
@@ -0,0 +1,97 @@
+use crate::geometry::field::{Field, VOLT_PER_METRE};
+use crate::geometry::material::Material;
+use crate::particle::particle::{Particle, ParticleType};
+use crate::utils::constants::KAPPA;
+use crate::utils::physics::{beta, dEdx, ke};
+
+// Limits on the length of the steps of each particle
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StepLimits {
+    pub max_energy_loss: f64,  // maximum fraction of the kinetic energy lost (or gained in an electric field) in a step
+    pub max_angle: f64,        // maximum multiple scattering angle in a step (rad)
+    pub max_bend: f64,         // maximum deflection by the magnetic field in a step (rad)
+    pub max_sagitta: f64,      // maximum distance between a step bent by the magnetic field and its chord (mm)
+    pub max_step: f64,         // maximum step length (mm)
+    pub min_step: f64,         // minimum step length (mm), so that particles always make progress
+}
+
+impl Default for StepLimits {
+    fn default() -> Self {
+        StepLimits { max_energy_loss: 0.05, max_angle: 0.1, max_bend: 0.1, max_sagitta: 0.01, max_step: 10.0, min_step: 1e-3 }
+    }
+}
+
+// Length of the next step of a particle (mm) in a material and a field, given the distance to the volume boundary along
+// its direction
+pub fn step_length(particle: &Particle, limits: &StepLimits, material: &Material, field: &Field, boundary: f64) -> f64 {
+    let mut length = f64::min(limits.max_step, boundary);
+    if !matches!(particle.species, ParticleType::Gamma | ParticleType::Neutron | ParticleType::OpticalPhoton) {
+        // Continuous energy loss
+        length = length.min(limits.max_energy_loss * ke(particle) / dEdx(particle, material));
+        // Multiple scattering: Highland angle, without the logarithmic correction
+        let theta = 13.6 / (beta(particle, material) * particle.state.p.mag());
+        length = length.min(material.radiation_length() * (limits.max_angle / theta).powi(2));
+        // Curvature in the magnetic field at the particle: the direction turns at KAPPA |q| |p x B| / p^2 rad/mm, and a
+        // step of length L is at most L^2 curvature / 8 away from its chord, so that it cannot go round a boundary
+        let (p, q) = (particle.state.p, particle.species.charge().abs());
+        let curvature = KAPPA * q * p.cross(field.magnetic(particle.state.r)).mag() / p.dot(p);
+        if curvature > 0.0 {
+            length = length.min(limits.max_bend / curvature).min((8.0 * limits.max_sagitta / curvature).sqrt());
+        }
+        // Acceleration in the electric field: at most q |E| along the step
+        let force = q * field.electric(particle.state.r).mag() * VOLT_PER_METRE;
+        if force > 0.0 {
+            length = length.min(limits.max_energy_loss * ke(particle) / force);
+        }
+    }
+    length.max(limits.min_step)
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use crate::geometry::material::WATER;
+
+    use crate::geometry::field::FieldMap;
+    use crate::utils::vec3::Vec3;
+
+    const NO_FIELD: &Field = &Field::Uniform(Vec3(0.0, 0.0, 0.0));
+
+    #[test]
+    fn test_stepping_step_length() {
+        let limits = StepLimits::default();
+        // Photons are only limited by the step length cap and the boundary
+        let gamma = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 1.0), ParticleType::Gamma);
+        assert_relative_eq!(step_length(&gamma, &limits, &WATER, NO_FIELD, 100.0), 10.0);
+        assert_relative_eq!(step_length(&gamma, &limits, &WATER, NO_FIELD, 2.0), 2.0);
+        assert_relative_eq!(step_length(&gamma, &limits, &WATER, NO_FIELD, 0.0), 1e-3);
+        // Low energy electrons are limited by multiple scattering
+        let electron = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 1.0), ParticleType::Electron);
+        let theta = 13.6 / (beta(&electron, &WATER) * 1.0);
+        assert_relative_eq!(step_length(&electron, &limits, &WATER, NO_FIELD, 100.0), WATER.radiation_length() * (0.1 / theta).powi(2));
+        // Slow protons are limited by energy loss
+        let proton = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 300.0), ParticleType::Proton);
+        assert_relative_eq!(step_length(&proton, &limits, &WATER, NO_FIELD, 100.0), 0.05 * ke(&proton) / dEdx(&proton, &WATER));
+        // Fast muons by the step length cap
+        let muon = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 1000.0), ParticleType::Muon);
+        assert_relative_eq!(step_length(&muon, &limits, &WATER, NO_FIELD, 100.0), 10.0);
+        // Tighter limits give shorter steps
+        let tight = StepLimits { max_energy_loss: 0.01, max_angle: 0.02, ..limits };
+        assert!(step_length(&proton, &tight, &WATER, NO_FIELD, 100.0) < step_length(&proton, &limits, &WATER, NO_FIELD, 100.0));
+        assert!(step_length(&electron, &tight, &WATER, NO_FIELD, 100.0) < step_length(&electron, &limits, &WATER, NO_FIELD, 100.0));
+        // Fast muons in a strong field are limited by the curvature, unless they move along the field
+        let (long, field) = (StepLimits { max_step: 1000.0, max_sagitta: 1000.0, ..limits }, &Field::Uniform(Vec3(0.0, 3.0, 0.0)));
+        assert_relative_eq!(step_length(&muon, &long, &WATER, field, 1000.0), 0.1 * 1000.0 / (KAPPA * 3.0));
+        let flat = StepLimits { max_sagitta: 0.01, ..long };
+        assert_relative_eq!(step_length(&muon, &flat, &WATER, field, 1000.0), (8.0 * 0.01 * 1000.0 / (KAPPA * 3.0)).sqrt());
+        let along = &Field::Uniform(Vec3(0.0, 0.0, 3.0));
+        assert_relative_eq!(step_length(&muon, &long, &WATER, along, 1000.0), step_length(&muon, &long, &WATER, NO_FIELD, 1000.0));
+        assert_relative_eq!(step_length(&gamma, &long, &WATER, field, 1000.0), 1000.0);
+        // and in a strong electric field by the energy gained
+        let electric = &Field::Map(FieldMap::parse_csv("0 0 -1 0 0 0 0 0 1e9\n0 0 1 0 0 0 0 0 1e9\n").unwrap());
+        assert_relative_eq!(step_length(&muon, &long, &WATER, electric, 1000.0), 0.05 * ke(&muon));
+    }
+}
@@ -0,0 +1,85 @@
+use serde::Serialize;
+
+use crate::particle::particle::{Particle, ParticleType, Process};
+use crate::utils::vec3::Vec3;
+
+// Point of a trajectory, at the end of a step
+#[derive(Debug, Clone, Serialize)]
+pub struct StepPoint {
+    pub position: Vec3,  // position (mm)
+    pub time: f64,       // time (ns)
+    pub momentum: Vec3,  // momentum at the end of the step (MeV)
+    pub edep: f64,       // energy deposited during the step (MeV)
+    pub process: Option<Process>,  // process which ended the life of the particle at this point, if any
+}
+
+// Trajectory of a particle, from its creation to the end of its last step
+#[derive(Debug, Clone, Serialize)]
+pub struct Trajectory {
+    pub id: usize,                 // track ID
+    pub parent_id: Option<usize>,  // track ID of the parent (None for primaries)
+    pub species: ParticleType,
+    pub creator: Process,
+    pub points: Vec<StepPoint>,
+}
+
+impl Trajectory {
+    // Start the trajectory of a particle at its creation point
+    pub fn new(particle: &Particle) -> Self {
+        let start = StepPoint { position: particle.state.r, time: particle.state.t, momentum: particle.state.p, edep: 0.0, process: None };
+        Trajectory { id: particle.id, parent_id: particle.parent_id, species: particle.species, creator: particle.creator, points: vec![start] }
+    }
+
+    // Add the current state of a particle, with the energy it deposited since the last point
+    pub fn add_point(&mut self, particle: &Particle, edep: f64) {
+        self.points.push(StepPoint { position: particle.state.r, time: particle.state.t, momentum: particle.state.p, edep, process: particle.end_process });
+    }
+
+    // Total length of the trajectory (mm)
+    pub fn length(&self) -> f64 {
+        self.points.windows(2).map(|pair| (pair[1].position - pair[0].position).mag()).sum()
+    }
+
+    // Total energy deposited along the trajectory (MeV)
+    pub fn edep(&self) -> f64 {
+        self.points.iter().map(|point| point.edep).sum()
+    }
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use crate::assert_vec3_eq;
+
+    #[test]
+    fn test_trajectory() {
+        let mut particle = Particle::new(Vec3(1.0, 2.0, 3.0), Vec3(0.0, 4.0, 0.0), ParticleType::Proton);
+        particle.id = 3;
+        particle.parent_id = Some(1);
+        particle.creator = Process::Decay;
+        let mut trajectory = Trajectory::new(&particle);
+        assert_eq!(trajectory.id, 3);
+        assert_eq!(trajectory.parent_id, Some(1));
+        assert_eq!(trajectory.species, ParticleType::Proton);
+        assert_eq!(trajectory.creator, Process::Decay);
+        assert_eq!(trajectory.points.len(), 1);
+        assert_vec3_eq!(trajectory.points[0].position, Vec3(1.0, 2.0, 3.0));
+
+        particle.state.r = Vec3(1.0, 5.0, 3.0);
+        particle.state.t = 0.5;
+        trajectory.add_point(&particle, 0.2);
+        particle.state.r = Vec3(1.0, 5.0, 7.0);
+        particle.state.t = 1.0;
+        particle.end_process = Some(Process::Decay);
+        trajectory.add_point(&particle, 0.3);
+        assert_eq!(trajectory.points.len(), 3);
+        assert_eq!(trajectory.points[1].process, None);
+        assert_eq!(trajectory.points[2].process, Some(Process::Decay));
+        assert_relative_eq!(trajectory.points[2].time, 1.0);
+        assert_relative_eq!(trajectory.length(), 7.0);
+        assert_relative_eq!(trajectory.edep(), 0.5);
+    }
+}
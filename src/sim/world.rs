@@ -1,29 +1,114 @@
-use rand::{rngs::StdRng, SeedableRng};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
-use crate::particle::particle::Particle;
-use crate::geometry::volume::Volume;
+use crate::particle::particle::{Particle, ParticleType, PhotonMode, Scintillation};
+use crate::geometry::material::AIR;
+use crate::geometry::navigator::Navigator;
+use crate::geometry::volume::{Face, Volume};
+use crate::geometry::fibre::FibreLattice;
+use crate::geometry::field::Field;
 use crate::utils::vec3::Vec3;
-use crate::utils::physics::ke;
+use crate::sim::stepping::{step_length, StepLimits};
+use crate::sim::trajectory::Trajectory;
+use crate::utils::physics::{beta, ke};
+use crate::utils::constants::{C, HC};
+
+// Optical photon detected on the surface of the volume
+#[derive(Debug, Clone)]
+pub struct PhotonHit {
+    pub id: usize,        // track ID of the photon
+    pub position: Vec3,   // position on the surface (mm)
+    pub time: f64,        // detection time (ns)
+    pub wavelength: f64,  // wavelength (nm)
+}
+
+// Particle leaving the volume
+#[derive(Debug, Clone)]
+pub struct Exit {
+    pub id: usize,        // track ID of the particle
+    pub position: Vec3,   // exit point, on the surface of the volume (mm)
+    pub momentum: Vec3,   // momentum when leaving (MeV)
+    pub time: f64,        // exit time (ns)
+    pub face: Face,       // face of the volume through which the particle left
+}
 
 pub struct World {
     time: f64,    // world time (ns)
-    pub dt: f64,  // time step (ns)
+    pub dt: f64,  // time step (ns): particles are synchronised at the end of each step
     pub particles: Vec<Particle>,
-    pub position_history: Vec<Vec3>,  // list of positions (temporary measure)
+    pub stack: Vec<Particle>,  // secondaries created during the current step
+    pub trajectories: Vec<Trajectory>,  // trajectory of each particle, indexed by track ID
     pub volume: Volume,
+    pub limits: StepLimits,  // limits on the length of the steps of each particle
+    pub straggling: bool,  // whether the energy lost by charged particles in each step fluctuates around its mean
+    pub field: Field,  // magnetic and electric field: uniform, or maps on a grid
+    pub cherenkov: PhotonMode,  // simulation mode of the Cherenkov light
+    pub scintillation: Scintillation,  // scintillation properties of the scintillating materials
+    pub detection_efficiency: f64,  // probability for an optical photon leaving the volume to be detected
+    pub hits: Vec<PhotonHit>,  // optical photons detected so far
+    pub fibres: Option<FibreLattice>,  // wavelength-shifting fibres capturing the optical photons (LiquidO)
+    pub exits: Vec<Exit>,  // particles which left the volume
     pub rng: StdRng,
 }
 
 impl World {
     pub fn new(particle_list: Vec<Particle>, vol: Volume, timestep: f64, random_seed: u64) -> Self {
-        World {
+        let mut world = World {
             time: 0.0,
             dt: timestep,
-            particles: particle_list,
-            position_history: vec![],
+            particles: vec![],
+            stack: vec![],
+            trajectories: vec![],
             volume: vol,
+            limits: StepLimits::default(),
+            straggling: false,
+            field: Field::default(),
+            cherenkov: PhotonMode::Count,
+            scintillation: Scintillation::NONE,
+            detection_efficiency: 0.25,
+            hits: vec![],
+            fibres: None,
+            exits: vec![],
             rng: StdRng::seed_from_u64(random_seed)
+        };
+        for particle in particle_list {
+            world.add_particle(particle);
         }
+        world
+    }
+
+    // Add a particle to the simulation, assigning it the next track ID
+    pub fn add_particle(&mut self, mut particle: Particle) {
+        particle.id = self.particles.len();
+        self.trajectories.push(Trajectory::new(&particle));
+        self.particles.push(particle);
+    }
+
+    // Particles created by the particle with track ID `id`
+    pub fn daughters(&self, id: usize) -> impl Iterator<Item = &Particle> {
+        self.particles.iter().filter(move |p| p.parent_id == Some(id))
+    }
+
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    // Total number of Cherenkov photons emitted so far
+    pub fn cherenkov_count(&self) -> u64 {
+        self.particles.iter().map(|p| p.n_cherenkov).sum()
+    }
+
+    // Total number of scintillation photons emitted so far
+    pub fn scintillation_count(&self) -> u64 {
+        self.particles.iter().map(|p| p.n_scintillation).sum()
+    }
+
+    // Total kinetic energy carried out of the volume by the particles which left it (MeV)
+    pub fn escaped_energy(&self) -> f64 {
+        self.exits.iter().map(|exit| {
+            let particle = &self.particles[exit.id];
+            let m = particle.state.m;
+            (exit.momentum.mag().powi(2) + m*m).sqrt() - m
+        }).sum()
     }
 
     pub fn has_alive_particles(&self) -> bool {
@@ -31,30 +116,90 @@ impl World {
     }
 
     pub fn step(&mut self) {
+        // If all the alive particles are only created later (e.g. delayed decay products), jump to the first one
+        let t_next = self.particles.iter().filter(|p| p.state.alive).map(|p| p.state.t).fold(f64::INFINITY, f64::min);
+        if t_next.is_finite() && t_next > self.time {
+            self.time = t_next;
+        }
+
+        let t_end = self.time + self.dt;
+        let navigator = Navigator::new(&self.volume);
         for particle in &mut self.particles {
-            // Check if particle KE is below 10keV
-            if ke(&particle) < 0.01 {
-                particle.state.alive = false;
-                continue;
-            }
+            // Advance the particle up to the end of the step (particles which are only created after this step are
+            // ignored), in steps limited by the physics and the geometry
+            while particle.state.alive && particle.state.t < t_end {
+                // Stop the particle if its KE is below 10keV (optical photons have much lower energies)
+                if particle.species != ParticleType::OpticalPhoton && ke(particle) < 0.01 {
+                    let edep = ke(particle);  // the remaining kinetic energy is deposited
+                    let material = navigator.locate(particle.state.r).map_or(&self.volume.material, |location| &location.volume.material);
+                    self.stack.extend(particle.stop(&mut self.rng, material));
+                    self.trajectories[particle.id].add_point(particle, edep);  // at the end of its life at rest
+                    continue;
+                }
 
-            // Ignore if particle is dead
-            if !particle.state.alive {
-                continue;
-            }
+                // Find the volume the particle is in, and use the physics of its material
+                let dir = particle.state.p.norm();
+                let Some(location) = navigator.locate_moving(particle.state.r, dir) else {
+                    particle.state.alive = false;  // created outside the world
+                    continue;
+                };
+                let material = &location.volume.material;
 
-            // Propagate the particle
-            particle.propagate(self.dt);
-            self.position_history.push(particle.state.r);
+                // Propagate the particle, the step being cut at the next boundary (but not below the minimum step)
+                let boundary = navigator.distance_to_boundary(&location, particle.state.r, dir);
+                let dx = step_length(particle, &self.limits, material, &self.field, boundary);
+                let speed = beta(particle, material) * C;
+                let dt = f64::min(dx / speed, t_end - particle.state.t);
+                let (r_pre, t_pre) = (particle.state.r, particle.state.t);
+                particle.propagate(dt, material, &self.field);
+                self.trajectories[particle.id].add_point(particle, 0.0);
 
-            // Check if particle is out of bounds
-            if !self.volume.contains(&particle) {
-                particle.state.alive = false;
-                continue;
+                // Optical photons passing close to a fibre are captured
+                if particle.species == ParticleType::OpticalPhoton && let Some(fibres) = &mut self.fibres
+                    && fibres.capture(&mut self.rng, r_pre, particle.state.r, t_pre, particle.state.t) {
+                    particle.state.alive = false;
+                    continue;
+                }
+
+                // Boundary crossing: optical photons can be reflected back at the boundary, otherwise particles enter
+                // the next volume, or leave the world (where optical photons are detected or killed). The end point is
+                // located along the direction at the end of the step, which the field may have turned.
+                let next = navigator.locate_moving(particle.state.r, particle.state.p.norm());
+                if next != Some(location) {
+                    let optical = particle.species == ParticleType::OpticalPhoton;
+                    let crossed = !optical || {
+                        let normal = navigator.normal(&location, next.as_ref(), particle.state.r);
+                        let n2 = next.map_or(AIR.n, |next| next.volume.material.n);
+                        particle.cross_boundary(&mut self.rng, normal, material.n, n2)
+                    };
+                    if crossed && next.is_none() {
+                        particle.state.alive = false;
+                        let face = self.volume.face(particle.state.r);
+                        self.exits.push(Exit { id: particle.id, position: particle.state.r, momentum: particle.state.p, time: particle.state.t, face });
+                        if optical && self.rng.random::<f64>() < self.detection_efficiency {
+                            self.hits.push(PhotonHit { id: particle.id, position: particle.state.r, time: particle.state.t, wavelength: HC / particle.state.p.mag() });
+                        }
+                        continue;
+                    }
+                }
+
+                // Interact the particle with the material it crossed, keeping its secondaries for the next step
+                let edep_pre = particle.edep;
+                let scintillation = if material.scintillator { &self.scintillation } else { &Scintillation::NONE };
+                self.stack.extend(particle.interact(&mut self.rng, material, dt, self.cherenkov, scintillation, self.straggling));
+                let point = self.trajectories[particle.id].points.last_mut().unwrap();
+                point.momentum = particle.state.p;
+                point.edep = particle.edep - edep_pre;
+                point.process = particle.end_process;
             }
+        }
 
-            // Interact the particle
-            particle.interact(&mut self.rng, self.volume.X0, self.dt);
+        self.time += self.dt;
+
+        // Start tracking the secondaries
+        let secondaries = std::mem::take(&mut self.stack);
+        for particle in secondaries {
+            self.add_particle(particle);
         }
     }
 }
@@ -64,13 +209,20 @@ impl World {
 #[cfg(test)]
 mod tests{
     use super::*;
-    use crate::particle::particle::ParticleType;
+    use crate::particle::particle::Process;
+    use crate::utils::constants::{C, KAPPA, Me};
+    use crate::geometry::material::{LAB, WATER};
+    use crate::geometry::shape::{Cylinder, Sphere};
+    use crate::geometry::field::FieldMap;
+    use crate::geometry::fibre::Axis;
     use crate::utils::vec3::Vec3;
+    use approx::assert_relative_eq;
+    use crate::assert_vec3_eq;
 
     #[test]
     fn test_world_creation() {
-        let v1 = Volume::new(10.0, 53.2);
-        let v2 = Volume::new(28.4, 60.0);
+        let v1 = Volume::new(10.0, WATER);
+        let v2 = Volume::new(28.4, WATER);
         let p1 = Particle::new(Vec3(1.0, 2.0, -3.0), Vec3(5.0, 0.0, 0.0), ParticleType::Electron);
         let p2 = Particle::new(Vec3(4.2, -1.5, 5.1), Vec3(3.4, -2.0, 0.7), ParticleType::Muon);
         let p3 = Particle::new(Vec3(20.1, -10.3, -9.7), Vec3(-100.0, 0.0, -52.1), ParticleType::Gamma);
@@ -86,8 +238,8 @@ mod tests{
 
     #[test]
     fn test_world_has_alive_particles() {
-        let v1 = Volume::new(10.0, 53.2);
-        let v2 = Volume::new(28.4, 60.0);
+        let v1 = Volume::new(10.0, WATER);
+        let v2 = Volume::new(28.4, WATER);
         let p1 = Particle::new(Vec3(1.0, 2.0, -3.0), Vec3(5.0, 0.0, 0.0), ParticleType::Electron);
         let p2 = Particle::new(Vec3(4.2, -1.5, 5.1), Vec3(3.4, -2.0, 0.7), ParticleType::Muon);
         let p3 = Particle::new(Vec3(20.1, -10.3, -9.7), Vec3(-100.0, 0.0, -52.1), ParticleType::Gamma);
@@ -100,4 +252,350 @@ mod tests{
         assert!(w3.has_alive_particles());
         assert!(!w4.has_alive_particles());
     }
+
+    #[test]
+    fn test_world_step_gamma() {
+        let v = Volume::new(500.0, WATER);
+        let p = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(1.0, 0.0, 0.0), ParticleType::Gamma);
+        let mut w = World::new(vec![p], v, 0.01, 7);
+        while w.has_alive_particles() {
+            w.step();
+        }
+        assert!(w.particles[0].state.p.mag() <= 1.0);
+    }
+
+    #[test]
+    fn test_world_secondaries() {
+        let v = Volume::new(500.0, WATER);
+        let p = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(1.0, 0.0, 0.0), ParticleType::Gamma);
+        let mut w = World::new(vec![p], v, 0.01, 4);
+        while w.has_alive_particles() {
+            w.step();
+        }
+        assert!(w.particles.len() > 1);
+        assert!(w.stack.is_empty());
+        for (i, particle) in w.particles.iter().enumerate() {
+            assert_eq!(particle.id, i);
+            match particle.parent_id {
+                None         => assert_eq!(particle.creator, Process::Primary),
+                Some(parent) => {
+                    assert!(parent < i);
+                    assert_eq!(w.particles[parent].species, ParticleType::Gamma);
+                    assert_ne!(particle.creator, Process::Primary);
+                },
+            }
+        }
+        assert!(w.daughters(0).count() >= 1);
+        assert!(w.daughters(0).all(|d| d.species == ParticleType::Electron));
+    }
+
+    #[test]
+    fn test_world_pair_production() {
+        let v = Volume::new(10000.0, WATER);
+        let p = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(50.0, 0.0, 0.0), ParticleType::Gamma);
+        let mut w = World::new(vec![p], v, 0.01, 6);
+        while w.has_alive_particles() {
+            w.step();
+        }
+        let positrons = w.particles.iter().filter(|p| p.species == ParticleType::Positron).collect::<Vec<_>>();
+        assert!(!positrons.is_empty());
+        for positron in positrons {
+            assert_eq!(positron.creator, Process::PairProduction);
+            assert!(w.daughters(positron.id).all(|d| d.species == ParticleType::Gamma));
+        }
+    }
+
+    #[test]
+    fn test_world_cherenkov() {
+        let v = Volume::new(100.0, WATER);
+        let p = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 5.0), ParticleType::Electron);
+        let mut w1 = World::new(vec![p.clone()], v.clone(), 0.01, 3);
+        while w1.has_alive_particles() {
+            w1.step();
+        }
+        assert!(w1.cherenkov_count() > 100);
+        assert!(w1.particles.iter().all(|p| p.species != ParticleType::OpticalPhoton));
+
+        let mut w2 = World::new(vec![p], v, 0.01, 3);
+        w2.cherenkov = PhotonMode::Full;
+        while w2.has_alive_particles() {
+            w2.step();
+        }
+        let photons = w2.particles.iter().filter(|p| p.species == ParticleType::OpticalPhoton).count();
+        assert_eq!(photons as u64, w2.cherenkov_count());
+    }
+
+    #[test]
+    fn test_world_scintillation() {
+        let v = Volume::new(100.0, LAB);
+        let p = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 1.0), ParticleType::Electron);
+        let mut w = World::new(vec![p], v, 0.01, 8);
+        w.cherenkov = PhotonMode::Off;
+        w.scintillation = Scintillation { mode: PhotonMode::Full, light_yield: 1000.0, ..Scintillation::LAB };
+        while w.has_alive_particles() {
+            w.step();
+        }
+        // All the kinetic energy (0.61 MeV) is deposited and turned into light
+        let photons = w.particles.iter().filter(|p| p.creator == Process::Scintillation).collect::<Vec<_>>();
+        assert_eq!(photons.len() as u64, w.scintillation_count());
+        assert_relative_eq!(photons.len() as f64, 600.0, max_relative = 0.1);
+        assert!(photons.iter().all(|p| p.state.t >= 0.0));
+    }
+
+    #[test]
+    fn test_world_photon_detection() {
+        let v = Volume::new(1000.0, WATER);
+        let mut photons = vec![];
+        for i in 0..1000 {
+            let dir = Vec3(f64::cos(i as f64), f64::sin(i as f64), 2.0 * (i as f64 / 1000.0) - 1.0).norm();
+            photons.push(Particle::new(Vec3(0.0, 0.0, 0.0), dir * (HC / 420.0), ParticleType::OpticalPhoton));
+        }
+        let mut w = World::new(photons, v, 0.1, 12);
+        w.detection_efficiency = 1.0;
+        while w.has_alive_particles() {
+            w.step();
+        }
+        // Most photons leave the volume after a few reflections, a few are absorbed
+        assert!(w.hits.len() > 950 && w.hits.len() < 1000);
+        for hit in &w.hits {
+            assert_relative_eq!(hit.position.0.abs().max(hit.position.1.abs()).max(hit.position.2.abs()), 500.0, max_relative = 1e-9);
+            assert!(hit.time > 500.0 / (C / 1.33));
+            assert_relative_eq!(hit.wavelength, 420.0, max_relative = 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_world_fibres() {
+        let v = Volume::new(200.0, LAB);
+        let p = Particle::new(Vec3(0.0, 0.0, -100.0), Vec3(0.0, 0.0, 300.0), ParticleType::Muon);
+        let mut w = World::new(vec![p], v, 0.01, 10);
+        w.cherenkov = PhotonMode::Off;
+        w.scintillation = Scintillation { mode: PhotonMode::Full, light_yield: 100.0, ..Scintillation::LAB };
+        w.fibres = Some(FibreLattice::new(200.0, 10.0, 0.5, 3500.0));
+        while w.has_alive_particles() {
+            w.step();
+        }
+        // The light is collected by the fibres close to the track
+        let fibres = w.fibres.unwrap();
+        let hit_fibres = fibres.fibres.iter().filter(|fibre| !fibre.hits.is_empty()).collect::<Vec<_>>();
+        assert!(fibres.hit_counts().iter().sum::<usize>() > 100);
+        assert!(hit_fibres.iter().all(|fibre| fibre.hits.iter().all(|&t| t > 0.0)));
+        let hits_near_track = hit_fibres.iter().filter(|fibre| fibre.axis != Axis::Z || fibre.u.abs() < 10.0 && fibre.v.abs() < 10.0)
+                                               .map(|fibre| fibre.hits.len()).sum::<usize>();
+        assert!(hits_near_track > fibres.hit_counts().iter().sum::<usize>() / 2);
+    }
+
+    #[test]
+    fn test_world_step_convergence() {
+        // Path length of a 200 MeV proton: the result converges as the energy loss per step is reduced
+        let path_length = |max_energy_loss: f64| {
+            let p = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 644.4), ParticleType::Proton);
+            let mut w = World::new(vec![p], Volume::new(1000.0, WATER), 1.0, 2);
+            w.limits.max_energy_loss = max_energy_loss;
+            while w.has_alive_particles() {
+                w.step();
+            }
+            w.trajectories[0].length()
+        };
+        let (coarse, medium, fine) = (path_length(0.2), path_length(0.05), path_length(0.0125));
+        assert!((medium - fine).abs() < (coarse - fine).abs());
+        assert_relative_eq!(medium, fine, max_relative = 0.01);
+        // CSDA range from PSTAR: 259.6 mm
+        assert_relative_eq!(fine, 259.6, max_relative = 0.05);
+    }
+
+    #[test]
+    fn test_world_straggling() {
+        // Identical 200 MeV protons travel the same path length without straggling, and spread by about 1% of their
+        // range around it with straggling
+        let path_lengths = |straggling: bool| {
+            let protons = (0..20).map(|_| Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 644.4), ParticleType::Proton)).collect();
+            let mut w = World::new(protons, Volume::new(1000.0, WATER), 1.0, 4);
+            w.straggling = straggling;
+            while w.has_alive_particles() {
+                w.step();
+            }
+            (0..20).map(|id| w.trajectories[id].length()).collect::<Vec<f64>>()
+        };
+        let fixed = path_lengths(false);
+        for length in &fixed {
+            assert_relative_eq!(*length, fixed[0], max_relative = 1e-12);
+        }
+        let fluctuating = path_lengths(true);
+        let mean = fluctuating.iter().sum::<f64>() / 20.0;
+        let sigma = (fluctuating.iter().map(|length| (length - mean).powi(2)).sum::<f64>() / 20.0).sqrt();
+        assert_relative_eq!(mean, fixed[0], max_relative = 0.01);
+        assert!(sigma > 0.005 * mean && sigma < 0.02 * mean);
+    }
+
+    #[test]
+    fn test_world_muon_steps() {
+        // A 1 GeV muon crossing 1m of water only needs ~100 steps (a 0.0001ns fixed time step would need ~30000)
+        let p = Particle::new(Vec3(0.0, 0.0, -500.0), Vec3(0.0, 0.0, 1000.0), ParticleType::Muon);
+        let mut w = World::new(vec![p], Volume::new(1000.0, WATER), 1.0, 9);
+        while w.has_alive_particles() {
+            w.step();
+        }
+        let steps = w.trajectories[0].points.len();
+        assert!(steps > 100 && steps < 150);
+    }
+
+    #[test]
+    fn test_world_exit() {
+        let p1 = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 1000.0), ParticleType::Muon);
+        let p2 = Particle::new(Vec3(10.0, 0.0, 0.0), Vec3(-3.0, 4.0, 0.0), ParticleType::Gamma);
+        let mut w = World::new(vec![p1, p2], Volume::new(100.0, WATER), 1.0, 17);
+        w.cherenkov = PhotonMode::Off;
+        w.limits.max_step = 30.0;
+        while w.has_alive_particles() {
+            w.step();
+        }
+        // The muon leaves through the top face, the photon (if it did not interact) through the +y face
+        let muon_exit = w.exits.iter().find(|exit| exit.id == 0).unwrap();
+        assert_eq!(muon_exit.face, Face::PosZ);
+        assert_relative_eq!(muon_exit.position.2, 50.0, max_relative = 1e-12);
+        assert_vec3_eq!(muon_exit.position, w.particles[0].state.r);
+        assert_relative_eq!(muon_exit.time, w.particles[0].state.t);
+        assert!(muon_exit.momentum.2 > 980.0);
+        if let Some(gamma_exit) = w.exits.iter().find(|exit| exit.id == 1 && w.particles[1].state.p.mag() == 5.0) {
+            assert_eq!(gamma_exit.face, Face::PosY);
+            assert_vec3_eq!(gamma_exit.position, Vec3(-27.5, 50.0, 0.0));
+            assert_relative_eq!(gamma_exit.time, 62.5 / C, max_relative = 1e-9);
+        }
+        // No step beyond the surface
+        assert!(w.trajectories.iter().flat_map(|t| &t.points).all(|point| {
+            let r = point.position;
+            r.0.abs().max(r.1.abs()).max(r.2.abs()) <= 50.0 + 1e-9
+        }));
+        assert!(w.escaped_energy() > ke(&w.particles[0]));
+    }
+
+    #[test]
+    fn test_world_min_step() {
+        // A photon 0.1 um below the surface still makes a full minimum step of 1 um, and leaves the world beyond it
+        let p = Particle::new(Vec3(0.0, 0.0, 50.0 - 1e-4), Vec3(0.0, 0.0, 1.0), ParticleType::Gamma);
+        let mut w = World::new(vec![p], Volume::new(100.0, WATER), 1.0, 3);
+        w.step();
+        assert_eq!(w.exits.len(), 1);
+        assert_relative_eq!(w.exits[0].position.2, 50.0 - 1e-4 + w.limits.min_step, max_relative = 1e-12);
+    }
+
+    #[test]
+    fn test_world_trajectories() {
+        let v = Volume::new(2000.0, WATER);
+        let p = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 5.0), ParticleType::Gamma);
+        let mut w = World::new(vec![p], v, 0.1, 6);
+        while w.has_alive_particles() {
+            w.step();
+        }
+        // One trajectory per particle, with the same history
+        assert_eq!(w.trajectories.len(), w.particles.len());
+        for (trajectory, particle) in w.trajectories.iter().zip(&w.particles) {
+            assert_eq!(trajectory.id, particle.id);
+            assert_eq!(trajectory.parent_id, particle.parent_id);
+            assert_eq!(trajectory.species, particle.species);
+            assert_eq!(trajectory.creator, particle.creator);
+            assert!(trajectory.points.windows(2).all(|pair| pair[1].time >= pair[0].time));
+            assert_vec3_eq!(trajectory.points.last().unwrap().position, particle.state.r);
+            assert_relative_eq!(trajectory.edep(), particle.edep, max_relative = 1e-9);
+        }
+        // Electrons which stop in the water deposit all their energy
+        let electron = w.trajectories.iter().find(|t| t.species == ParticleType::Electron && w.exits.iter().all(|exit| exit.id != t.id)).unwrap();
+        let ke_start = (electron.points[0].momentum.mag().powi(2) + Me*Me).sqrt() - Me;
+        let edep_daughters = w.daughters(electron.id).map(|d| d.edep).sum::<f64>();
+        assert!(electron.edep() + edep_daughters <= ke_start + 1e-9);
+        assert!(electron.edep() > 0.5 * ke_start);
+    }
+
+    #[test]
+    fn test_world_muon_decay() {
+        let v = Volume::new(500.0, WATER);
+        let p = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 30.0), ParticleType::Muon);
+        let mut w = World::new(vec![p], v, 0.001, 11);
+        assert_relative_eq!(w.time, 0.0);
+        w.step();
+        assert_relative_eq!(w.time, 0.001);
+        while w.has_alive_particles() {
+            w.step();
+        }
+        // The muon stops after a few ns, then either decays or is captured at the end of its lifetime at rest: its
+        // products start from there, and the world clock jumps to them
+        let muon = &w.particles[0];
+        assert!(muon.state.t > 10.0);
+        for product in w.daughters(0) {
+            assert!(product.creator == Process::Decay || product.creator == Process::MuonCapture);
+            assert!(product.state.t > muon.state.t - 1e-9);
+            assert!(w.time >= product.state.t);
+        }
+        // The step history is timestamped
+        let points = &w.trajectories[0].points;
+        assert_relative_eq!(points[0].time, 0.0);
+        assert_relative_eq!(points[1].time, 0.001);
+        assert!(points.windows(2).all(|pair| pair[1].time >= pair[0].time));
+        // The last point records how the muon ended
+        let last = points.last().unwrap();
+        assert_relative_eq!(last.time, muon.state.t);
+        assert!(last.process == Some(Process::Decay) || last.process == Some(Process::MuonCapture));
+        assert!(points[..points.len() - 1].iter().all(|point| point.process.is_none()));
+    }
+
+    #[test]
+    fn test_world_volumes() {
+        // Scintillator tank inside a water shield inside air
+        let mut v = Volume::new(1000.0, AIR);
+        let mut shield = Volume::placed("shield", Cylinder::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 1.0), 300.0, 400.0), WATER, Vec3(0.0, 0.0, 0.0));
+        shield.add_daughter(Volume::placed("tank", Sphere::new(Vec3(0.0, 0.0, 0.0), 100.0), LAB, Vec3(0.0, 0.0, 0.0)));
+        v.add_daughter(shield);
+        let p = Particle::new(Vec3(0.0, 0.0, -490.0), Vec3(0.0, 0.0, 1000.0), ParticleType::Muon);
+        let mut w = World::new(vec![p], v, 0.1, 11);
+        w.cherenkov = PhotonMode::Off;
+        w.scintillation = Scintillation { mode: PhotonMode::Full, light_yield: 10.0, ..Scintillation::LAB };
+        while w.has_alive_particles() {
+            w.step();
+        }
+
+        // The steps stop at every boundary
+        let points = &w.trajectories[0].points;
+        for z in [-400.0, -100.0, 100.0, 400.0] {
+            assert!(points.iter().any(|point| (point.position.2 - z).abs() < 1e-6));
+        }
+        // The energy is deposited in the water and the scintillator, but hardly in the air
+        let edep = |z0: f64, z1: f64| points.iter().filter(|point| point.position.2 > z0 && point.position.2 <= z1).map(|point| point.edep).sum::<f64>();
+        assert!(edep(-490.0, -400.0) < 0.1);
+        assert_relative_eq!(edep(-400.0, -100.0), 300.0 * 0.21, max_relative = 0.15);
+        assert_relative_eq!(edep(-100.0, 100.0), 200.0 * 0.18, max_relative = 0.15);
+        // Only the scintillator produces light
+        let photons = w.particles.iter().filter(|p| p.creator == Process::Scintillation && p.parent_id == Some(0)).collect::<Vec<_>>();
+        assert!(photons.len() > 200);
+        assert!(photons.iter().all(|p| w.trajectories[p.id].points[0].position.2.abs() <= 100.0 + 1e-6));
+        // Photons escaping the tank go through the water, some of them all the way out of the world
+        assert!(photons.iter().any(|p| w.trajectories[p.id].points.iter().any(|point| point.position.mag() > 150.0)));
+        assert!(w.exits.iter().any(|exit| w.particles[exit.id].species == ParticleType::OpticalPhoton));
+    }
+
+    #[test]
+    fn test_world_magnetic_field() {
+        // Muons of both charges in air bend in opposite directions, on circles of radius p / (KAPPA |q| B), in a
+        // uniform field or in the same field given by a map
+        let corners = (0..8).map(|i| [(i >> 2) & 1, (i >> 1) & 1, i & 1].map(|c| if c == 0 { -2000 } else { 2000 }))
+            .map(|[x, y, z]| format!("{x},{y},{z},0,0,1\n")).collect::<String>();
+        for field in [Field::Uniform(Vec3(0.0, 0.0, 1.0)), Field::Map(FieldMap::parse_csv(&corners).unwrap())] {
+            let muon = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(300.0, 0.0, 0.0), ParticleType::Muon);
+            let antimuon = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(300.0, 0.0, 0.0), ParticleType::AntiMuon);
+            let mut w = World::new(vec![muon, antimuon], Volume::new(2000.0, AIR), 1.0, 3);
+            w.field = field;
+            for _ in 0..3 {
+                w.step();
+            }
+            let radius = 300.0 / KAPPA;
+            for (id, side) in [(0, 1.0), (1, -1.0)] {
+                let points = &w.trajectories[id].points;
+                assert!(points.len() > 10);
+                assert!(points.last().unwrap().position.1 * side > 300.0);
+                for point in points {
+                    assert_relative_eq!((point.position - Vec3(0.0, side * radius, 0.0)).mag(), radius, max_relative = 0.01);
+                }
+            }
+        }
+    }
 }
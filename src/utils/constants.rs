@@ -1,4 +1,22 @@
+#![allow(non_upper_case_globals)]  // physics notation (Me, Mmu, ...)
+
 pub const C: f64 = 299.792_458;  // mm/ns
+pub const KAPPA: f64 = 0.299_792_458;  // p = KAPPA q B R, for a momentum p (MeV), charge q (e), field B (T) and radius of curvature R (mm)
 pub const Me: f64 = 0.511;  // electron mass (MeV)
 pub const Mmu: f64 = 105.66;  // muon mass (MeV)
 pub const Mg: f64 = 0.0;  // gamma mass (MeV)
+pub const Mn: f64 = 939.565;  // neutron mass (MeV)
+pub const Mpi: f64 = 139.570;  // charged pion mass (MeV)
+pub const Mp: f64 = 938.272;  // proton mass (MeV)
+pub const Malpha: f64 = 3727.379;  // alpha particle mass (MeV)
+pub const Re: f64 = 2.817_940_326e-12;  // classical electron radius (mm)
+pub const K: f64 = 0.307_075;  // Bethe-Bloch coefficient 4*pi*N_A*r_e^2*m_e*c^2 (MeV cm^2/mol)
+pub const BREMS_CUT: f64 = 0.1;  // minimum energy of emitted bremsstrahlung photons (MeV)
+pub const TAU_MU: f64 = 2_196.98;  // muon lifetime (ns)
+pub const TAU_PI: f64 = 26.033;  // charged pion lifetime (ns)
+pub const ALPHA: f64 = 1.0 / 137.035_999;  // fine structure constant
+pub const HC: f64 = 1.239_841_98e-3;  // Planck constant times speed of light (MeV nm)
+pub const LAMBDA_MIN: f64 = 300.0;  // shortest wavelength of the optical photons (nm)
+pub const LAMBDA_MAX: f64 = 600.0;  // longest wavelength of the optical photons (nm)
+pub const EULER: f64 = 0.577_215_665;  // Euler-Mascheroni constant
+pub const N_A: f64 = 6.022_140_76e23;  // Avogadro constant (1/mol)
@@ -1,16 +1,43 @@
 #[macro_export]
 macro_rules! assert_vec3_eq {
-    ($a:expr, $b:expr $(, $epsilon:expr)? ) => {
+    ($a:expr, $b:expr $(,)?) => {
+        assert!(
+            $a == $b,
+            "Vec3 not equal: left = {:?}, right = {:?}",
+            $a,
+            $b
+        );
+    };
+    ($a:expr, $b:expr, $epsilon:expr $(,)?) => {
         {
-            // default epsilon
-            let eps =  $( $epsilon )? 1e-12;
+            let (a, b, eps) = ($a, $b, $epsilon);
             assert!(
-                $a == $b,
+                (a - b).mag() <= eps,
                 "Vec3 not equal: left = {:?}, right = {:?}, epsilon = {}",
-                $a,
-                $b,
+                a,
+                b,
                 eps
             );
         }
     };
 }
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use crate::assert_vec3_eq;
+    use crate::utils::vec3::Vec3;
+
+    #[test]
+    fn test_macro_assert_vec3_eq() {
+        assert_vec3_eq!(Vec3(1.0, 2.0, 3.0), Vec3(1.0, 2.0, 3.0));
+        assert_vec3_eq!(Vec3(1.0, 2.0, 3.0), Vec3(1.0, 2.0 + 1e-10, 3.0), 1e-9);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_macro_assert_vec3_eq_epsilon() {
+        assert_vec3_eq!(Vec3(1.0, 2.0, 3.0), Vec3(1.0, 2.0 + 1e-8, 3.0), 1e-9);
+    }
+}
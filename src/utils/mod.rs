@@ -3,3 +3,4 @@ pub mod r#macro;
 pub mod physics;
 pub mod constants;
 pub mod operations;
+pub mod stopping;
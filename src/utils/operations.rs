@@ -1,3 +1,5 @@
+use rand::Rng;
+
 use crate::Vec3;
 
 // Returns the two missing vectors to create an orthonormal basis with `vec`
@@ -15,11 +17,42 @@ pub fn orthonormal_basis(vec: Vec3) -> (Vec3, Vec3) {
     (u, v)
 }
 
+// Sample an isotropic unit vector
+pub fn random_direction(rng: &mut impl Rng) -> Vec3 {
+    let cos_theta = 2.0 * rng.random::<f64>() - 1.0;
+    let phi = 2.0 * std::f64::consts::PI * rng.random::<f64>();
+    Vec3(0.0, 0.0, 1.0).rotate(cos_theta.acos(), phi)
+}
+
+// Boost a four-momentum (e, p) by the velocity beta (in units of c)
+pub fn lorentz_boost(e: f64, p: Vec3, beta: Vec3) -> (f64, Vec3) {
+    let b2 = beta.dot(beta);
+    if b2 == 0.0 {
+        return (e, p);
+    }
+    let gamma = 1.0 / (1.0 - b2).sqrt();
+    let bp = beta.dot(p);
+    (gamma * (e + bp), p + beta * ((gamma - 1.0) * bp / b2 + gamma * e))
+}
+
 // Calculate the 8th-degree "log polynomial"
 pub fn log_polynomial(p: f64, cs: Vec<f64>) -> f64 {
     cs.into_iter().enumerate().map(|(n, c)| c * f64::ln(p).powf(n as f64)).sum()
 }
 
+// Interpolate tabulated values linearly in log-log space, clamping outside the table
+pub fn log_log_interpolate(x: f64, xs: &[f64], ys: &[f64]) -> f64 {
+    if x <= xs[0] {
+        return ys[0];
+    }
+    if x >= xs[xs.len()-1] {
+        return ys[ys.len()-1];
+    }
+    let i = xs.iter().position(|&xi| xi > x).unwrap() - 1;
+    let t = f64::ln(x / xs[i]) / f64::ln(xs[i+1] / xs[i]);
+    f64::exp(f64::ln(ys[i]) + t * f64::ln(ys[i+1] / ys[i]))
+}
+
 
 // Tests
 #[cfg(test)]
@@ -27,6 +60,7 @@ mod tests {
     use super::*;
     use approx::assert_relative_eq;
     use crate::assert_vec3_eq;
+    use rand::{rngs::StdRng, SeedableRng};
 
     #[test]
     fn test_operations_orthonormal_basis() {
@@ -42,6 +76,32 @@ mod tests {
         assert_vec3_eq!(orthonormal_basis(v5), (Vec3(0.0, 0.9486832980505138, 0.31622776601683794), Vec3(-0.9035079029052513, 0.1355261854357877, -0.40657855630736306)));
     }
 
+    #[test]
+    fn test_operations_random_direction() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let dirs = (0..10000).map(|_| random_direction(&mut rng)).collect::<Vec<Vec3>>();
+        assert!(dirs.iter().all(|dir| (dir.mag() - 1.0).abs() < 1e-12));
+        let mean = dirs.iter().fold(Vec3(0.0, 0.0, 0.0), |acc, &dir| acc + dir) / dirs.len() as f64;
+        assert!(mean.mag() < 0.03);
+    }
+
+    #[test]
+    fn test_operations_lorentz_boost() {
+        // Particle at rest takes the boost velocity
+        let (e, p) = lorentz_boost(2.0, Vec3(0.0, 0.0, 0.0), Vec3(0.6, 0.0, 0.0));
+        assert_relative_eq!(e, 2.5);
+        assert_vec3_eq!(p, Vec3(1.5, 0.0, 0.0));
+        // Invariant mass is conserved, and boosting back returns the original four-momentum
+        let (e1, p1) = (5.0, Vec3(1.0, -2.0, 3.0));
+        let beta = Vec3(0.3, 0.4, -0.5);
+        let (e2, p2) = lorentz_boost(e1, p1, beta);
+        assert_relative_eq!(e2*e2 - p2.dot(p2), e1*e1 - p1.dot(p1), max_relative = 1e-12);
+        let (e3, p3) = lorentz_boost(e2, p2, -beta);
+        assert_relative_eq!(e3, e1, max_relative = 1e-12);
+        assert_relative_eq!((p3 - p1).mag(), 0.0, epsilon = 1e-12);
+        assert_vec3_eq!(lorentz_boost(e1, p1, Vec3(0.0, 0.0, 0.0)).1, p1);
+    }
+
     #[test]
     fn test_operations_log_polynomial() {
         let cs1 = vec![1.97185875, -4.90322067e-01, 5.67984147e-01, -3.78515229e-01, 1.96937857e-01, -6.69875048e-02, 1.30714285e-02, -1.31646064e-03, 5.29555090e-05];
@@ -57,4 +117,15 @@ mod tests {
         assert_relative_eq!(log_polynomial(400.0, cs2), -187889.94823150337);
         assert_relative_eq!(log_polynomial(400.0, cs3), 1.9963390591128984);
     }
+
+    #[test]
+    fn test_operations_log_log_interpolate() {
+        let xs = [1.0, 10.0, 100.0];
+        let ys = [2.0, 20.0, 50.0];
+        assert_relative_eq!(log_log_interpolate(1.0, &xs, &ys), 2.0);
+        assert_relative_eq!(log_log_interpolate(10.0, &xs, &ys), 20.0);
+        assert_relative_eq!(log_log_interpolate(f64::sqrt(10.0), &xs, &ys), 2.0 * f64::sqrt(10.0));
+        assert_relative_eq!(log_log_interpolate(0.5, &xs, &ys), 2.0);
+        assert_relative_eq!(log_log_interpolate(500.0, &xs, &ys), 50.0);
+    }
 }
@@ -1,5 +1,26 @@
+#![allow(non_snake_case)]  // physics notation (dEdx, X0, ...)
+
+use rand::Rng;
+use rand_distr::{Distribution, Normal, Poisson};
+
 use crate::particle::particle::{Particle, ParticleType};
-use crate::utils::operations::log_polynomial;
+use crate::geometry::material::{Material, WATER};
+use crate::utils::constants::{ALPHA, EULER, K, LAMBDA_MAX, LAMBDA_MIN, Me, Mmu, Re, TAU_MU};
+use crate::utils::operations::{log_polynomial, log_log_interpolate};
+use crate::utils::stopping::Projectile;
+
+// Measured lifetimes of negative muons bound in atoms, including nuclear capture: atomic number and lifetime (ns)
+// (Suzuki, Measday & Roalsvig 1987)
+const BOUND_MUON_LIFETIMES: [(f64, f64); 10] = [
+    (1.0, 2194.9), (6.0, 2026.3), (7.0, 1906.8), (8.0, 1795.4), (13.0, 864.0),
+    (14.0, 756.0), (20.0, 332.7), (26.0, 206.0), (29.0, 163.5), (82.0, 74.8),
+];
+
+// K-shell binding energies: atomic number and binding energy (MeV) (X-ray data booklet)
+const K_SHELL_BINDING: [(f64, f64); 11] = [
+    (1.0, 13.6e-6), (6.0, 284.2e-6), (7.0, 409.9e-6), (8.0, 543.1e-6), (13.0, 1.5596e-3), (14.0, 1.8389e-3),
+    (18.0, 3.2059e-3), (20.0, 4.0381e-3), (26.0, 7.112e-3), (29.0, 8.979e-3), (82.0, 88.005e-3),
+];
 
 // Get particle energy
 pub fn energy(particle: &Particle) -> f64 {
@@ -8,33 +29,81 @@ pub fn energy(particle: &Particle) -> f64 {
 
 // Get gamma factor of particle
 pub fn gamma(particle: &Particle) -> Option<f64> {
-    let energy = energy(&particle);
+    let energy = energy(particle);
     match particle.species {
-        ParticleType::Gamma => None,
-        _                   => Some(energy / particle.state.m),
+        ParticleType::Gamma | ParticleType::OpticalPhoton => None,
+        _                                                 => Some(energy / particle.state.m),
     }
 }
 
-// Get beta factor of particle
-pub fn beta(particle: &Particle) -> f64 {
+// Get beta factor of particle in a material
+pub fn beta(particle: &Particle, material: &Material) -> f64 {
     match particle.species {
-        ParticleType::Gamma => 1.0,
-        _                   => (1.0 - 1.0/gamma(&particle).expect("Division by gamma factor which is equal to 0.").powf(2.0)).sqrt(),
+        ParticleType::Gamma         => 1.0,
+        ParticleType::OpticalPhoton => 1.0 / material.n,  // speed of light in the medium
+        _                           => (1.0 - 1.0/gamma(particle).expect("Division by gamma factor which is equal to 0.").powf(2.0)).sqrt(),
     }
 }
 
-// Get dE/dx of ionizing particles (MeV/mm, hence the division by 10)
-pub fn dEdx(particle: &Particle) -> f64 {
+// Bethe-Bloch mean energy loss (MeV/mm) of a heavy charged particle of momentum p (MeV), mass m (MeV) and
+// charge z (in units of e), including the shell correction and neglecting the density effect
+pub fn bethe_bloch(p: f64, m: f64, z: f64, material: &Material) -> f64 {
+    let bg2 = (p / m).powf(2.0);  // (beta*gamma)^2
+    let beta2 = bg2 / (1.0 + bg2);
+    let T_max = max_energy_transfer(p, m);
+
+    // Shell correction C/Z (Barkas & Berger), only valid down to beta*gamma = 0.13
+    let eta = f64::max(bg2.sqrt(), 0.13);
+    let I_eV = material.I * 1e6;
+    let C = (0.422377*eta.powf(-2.0) + 0.0304043*eta.powf(-4.0) - 0.00038106*eta.powf(-6.0)) * 1e-6 * I_eV.powf(2.0)
+          + (3.858019*eta.powf(-2.0) - 0.1667989*eta.powf(-4.0) + 0.00157955*eta.powf(-6.0)) * 1e-9 * I_eV.powf(3.0);
+
+    let log_term = 0.5 * f64::ln(2.0 * Me * bg2 * T_max / material.I.powf(2.0));
+    K * z*z * material.Z_over_A() / beta2 * (log_term - beta2 - C/material.Z_eff()) * material.density * 0.1
+}
+
+// Maximum kinetic energy transfer to an electron in a collision (MeV), for a heavy particle of momentum p (MeV) and
+// mass m (MeV)
+pub fn max_energy_transfer(p: f64, m: f64) -> f64 {
+    let bg2 = (p / m).powf(2.0);
+    let ratio = Me / m;
+    2.0 * Me * bg2 / (1.0 + 2.0*(1.0 + bg2).sqrt()*ratio + ratio*ratio)
+}
+
+// Ratio of the stopping powers of a material and water at the same beta*gamma, from the Bethe-Bloch formula.
+// Used to scale the fits to the water data to other materials.
+fn stopping_ratio(bg: f64, material: &Material) -> f64 {
+    let p = f64::max(bg, 0.084) * Mmu;
+    bethe_bloch(p, Mmu, 1.0, material) / bethe_bloch(p, Mmu, 1.0, &WATER)
+}
+
+// Get dE/dx of ionizing particles from the stopping power tables of the material, if it has any for them (MeV/mm)
+fn tabulated_dEdx(particle: &Particle, material: &Material) -> Option<f64> {
+    let table = match particle.species {
+        ParticleType::Electron | ParticleType::Positron => material.stopping_table(Projectile::Electron),
+        _ => material.stopping_table(Projectile::Proton).or_else(|| material.stopping_table(Projectile::Alpha)),
+    }?;
+    Some(table.mass_stopping(ke(particle), particle.state.m, 1.0) * material.density * 0.1)
+}
+
+// Get dE/dx of ionizing particles in a material (MeV/mm, hence the division by 10). The stopping power tables of the
+// material are used if it has any, and the fits to the water tables otherwise.
+pub fn dEdx(particle: &Particle, material: &Material) -> f64 {
+    if let Some(dEdx) = tabulated_dEdx(particle, material) {
+        return dEdx;
+    }
     let momentum = particle.state.p.mag();
-    let value = match particle.species {
-        ParticleType::Electron if momentum < 0.103 => 8.0 * 0.1,  // constant energy loss below fit range
-        ParticleType::Electron                     => log_polynomial(momentum, vec![1.97185875, -4.90322067e-01, 5.67984147e-01, -3.78515229e-01, 1.96937857e-01, -6.69875048e-02, 1.30714285e-02, -1.31646064e-03, 5.29555090e-05]) * 0.1,
-        ParticleType::Muon if momentum < 8.9       => 8.0 * 0.1,  // constant energy loss below fit range
-        ParticleType::Muon if momentum < 50.0      => log_polynomial(momentum, vec![-2.21192313e+05, 4.16349323e+05, -3.02334049e+05, 9.22330794e+04, 1.78846389e+03, -9.81957228e+03, 2.97223872e+03, -3.90203242e+02, 1.99344973e+01]),
-        ParticleType::Muon if momentum >= 50.0     => log_polynomial(momentum, vec![1.13754387e+03, -1.13642381e+03, 4.96588219e+02, -1.23563655e+02, 1.91190645e+01, -1.88126582e+00, 1.14850292e-01, -3.97495919e-03, 5.96940644e-05]) * 0.1,
-        _                                          => unreachable!(),
-    };
-    value
+    let ratio = || stopping_ratio(momentum / particle.state.m, material);
+    match particle.species {
+        ParticleType::Electron | ParticleType::Positron if momentum < 0.103 => 8.0 * 0.1 * ratio(),  // constant energy loss below fit range
+        ParticleType::Electron | ParticleType::Positron                     => log_polynomial(momentum, vec![1.97185875, -4.90322067e-01, 5.67984147e-01, -3.78515229e-01, 1.96937857e-01, -6.69875048e-02, 1.30714285e-02, -1.31646064e-03, 5.29555090e-05]) * 0.1 * ratio(),
+        // Constant energy loss below the validity of Bethe-Bloch (beta*gamma = 0.084, i.e. 8.9 MeV/c for muons)
+        ParticleType::Muon | ParticleType::AntiMuon if momentum < 50.0      => bethe_bloch(f64::max(momentum, 0.084 * particle.state.m), particle.state.m, 1.0, material),
+        ParticleType::Muon | ParticleType::AntiMuon if momentum >= 50.0     => log_polynomial(momentum, vec![1.13754387e+03, -1.13642381e+03, 4.96588219e+02, -1.23563655e+02, 1.91190645e+01, -1.88126582e+00, 1.14850292e-01, -3.97495919e-03, 5.96940644e-05]) * 0.1 * ratio(),
+        // Constant energy loss below the validity of Bethe-Bloch (same beta*gamma as the muon cutoff)
+        ParticleType::PionPlus | ParticleType::PionMinus | ParticleType::Proton => bethe_bloch(f64::max(momentum, 0.084 * particle.state.m), particle.state.m, 1.0, material),
+        _                                                                       => unreachable!(),
+    }
 }
 
 // Get kinetic energy of a particle in MeV
@@ -44,12 +113,320 @@ pub fn ke(particle: &Particle) -> f64 {
     (p*p + m*m).sqrt() - m
 }
 
+// Lifetime of a negative muon bound in an atom of atomic number Z (ns). The nuclear capture rate, which shortens the
+// lifetime, is interpolated in log-log between the measured elements.
+pub fn bound_muon_lifetime(Z: f64) -> f64 {
+    let (zs, rates): (Vec<f64>, Vec<f64>) = BOUND_MUON_LIFETIMES.iter().map(|&(z, tau)| (z, 1.0/tau - 1.0/TAU_MU)).unzip();
+    1.0 / (1.0/TAU_MU + log_log_interpolate(Z, &zs, &rates))
+}
+
+// Binding energy of the K-shell electrons of an atom of atomic number Z (MeV), interpolated in log-log between the
+// tabulated elements
+pub fn k_shell_binding(Z: f64) -> f64 {
+    let (zs, energies): (Vec<f64>, Vec<f64>) = K_SHELL_BINDING.iter().copied().unzip();
+    log_log_interpolate(Z, &zs, &energies)
+}
+
+// Sample the energy lost by a charged particle along a step of length dx (mm), fluctuating around the mean loss (MeV).
+// The regime depends on the ratio kappa of the Landau width xi to the maximum energy transfer: Landau fluctuations for
+// thin steps (few collisions), Gaussian ones for thick steps (many collisions), and the Urban model in between.
+pub fn sample_energy_loss(rng: &mut impl Rng, particle: &Particle, material: &Material, mean: f64, dx: f64) -> f64 {
+    let (p, m, z) = (particle.state.p.mag(), particle.state.m, particle.species.charge());
+    let bg2 = (p / m).powf(2.0);
+    let beta2 = bg2 / (1.0 + bg2);
+    let T_max = match particle.species {
+        ParticleType::Electron => ke(particle) / 2.0,  // identical particles: the faster one is called the primary
+        ParticleType::Positron => ke(particle),
+        _                      => max_energy_transfer(p, m),
+    };
+    if mean <= 0.0 || T_max <= 0.0 {
+        return mean;
+    }
+
+    let xi = 0.5 * K * z*z * material.Z_over_A() * material.density * dx * 0.1 / beta2;
+    let kappa = xi / T_max;
+    if kappa > 10.0 {
+        // Gaussian with the Bohr variance, truncated to [0, 2 mean]
+        let normal = Normal::new(mean, (xi * T_max * (1.0 - beta2 / 2.0)).sqrt()).unwrap();
+        loop {
+            let loss = normal.sample(rng);
+            if (0.0..=2.0 * mean).contains(&loss) {
+                return loss;
+            }
+        }
+    } else if kappa < 0.01 {
+        // Landau distribution of width xi, shifted to the mean loss. The mean of the distribution truncated at lambda_max
+        // tends to ln(lambda_max) - (1 - EULER), so cutting its tail at exp(-beta^2) / kappa (close to the maximum energy
+        // transfer) keeps the mean loss.
+        let lambda_mean = -(1.0 - EULER) - beta2 - kappa.ln();
+        let lambda_max = f64::exp(-beta2) / kappa;
+        loop {
+            let lambda = sample_landau(rng);
+            if lambda <= lambda_max {
+                return f64::max(mean + xi * (lambda - lambda_mean), 0.0);
+            }
+        }
+    } else {
+        sample_urban(rng, material, mean, bg2, T_max)
+    }
+}
+
+// Urban model of the energy loss fluctuations: excitations of two atomic levels of energies E1 and E2, and ionisations
+// with an energy transfer distributed as 1/E^2 between E0 and T_max, sharing the mean loss
+fn sample_urban(rng: &mut impl Rng, material: &Material, mean: f64, bg2: f64, T_max: f64) -> f64 {
+    const RATE: f64 = 0.4;   // fraction of the mean loss due to ionisations
+    const E0: f64 = 1e-5;    // ionisation energy (MeV)
+    const N_HARD: f64 = 16.0;  // mean number of the hardest ionisations, sampled one by one
+    let poisson = |rng: &mut _, n: f64| if n > 0.0 { Poisson::new(n).unwrap().sample(rng) } else { 0.0 };
+    let beta2 = bg2 / (1.0 + bg2);
+    let log = |E: f64| f64::max(f64::ln(2.0 * Me * bg2 / E) - beta2, 0.0);
+
+    // Excitations: the levels share the logarithm of the mean excitation energy, ln I = f1 ln E1 + f2 ln E2
+    let mut loss = 0.0;
+    let Z = material.Z_eff();
+    let f2 = if Z <= 2.0 { 0.0 } else { 2.0 / Z };
+    let E2 = 1e-5 * Z*Z;
+    let E1 = f64::exp((material.I.ln() - f2 * E2.ln()) / (1.0 - f2));
+    if log(material.I) > 0.0 {
+        for (f, E) in [(1.0 - f2, E1), (f2, E2)] {
+            loss += poisson(rng, mean * (1.0 - RATE) * f * log(E) / (log(material.I) * E)) * E;
+        }
+    } else {
+        loss += mean * (1.0 - RATE);
+    }
+
+    // Ionisations: c (1/E - 1/T_max) collisions above E, the many soft ones being summed as a Gaussian
+    if T_max <= E0 {
+        return loss + mean * RATE;
+    }
+    let c = mean * RATE / (T_max / E0).ln();
+    let E_split = f64::max(E0, 1.0 / (N_HARD / c + 1.0 / T_max));
+    if E_split > E0 {
+        let soft = Normal::new(c * (E_split / E0).ln(), (c * (E_split - E0)).sqrt()).unwrap();
+        loss += f64::max(soft.sample(rng), 0.0);
+    }
+    for _ in 0..poisson(rng, c * (1.0 / E_split - 1.0 / T_max)) as u64 {
+        loss += 1.0 / (1.0 / E_split - rng.random::<f64>() * (1.0 / E_split - 1.0 / T_max));
+    }
+    loss
+}
+
+// Sample the standard Landau distribution (most probable value -0.2228), as a stable distribution of index 1 and
+// skewness 1 (Chambers-Mallows-Stuck method)
+pub fn sample_landau(rng: &mut impl Rng) -> f64 {
+    let v = std::f64::consts::PI * (0.5 - rng.random::<f64>());
+    let w = -f64::ln(1.0 - rng.random::<f64>());
+    (std::f64::consts::FRAC_PI_2 + v) * v.tan() - f64::ln(w * v.cos() / (std::f64::consts::FRAC_PI_2 + v))
+}
+
+// Photoelectric attenuation coefficient (1/mm): power law fit to NIST XCOM for water between 10 and 100 keV,
+// scaled to other materials with a cross section per atom going as Z^4.5
+pub fn photoelectric_mu(energy: f64, material: &Material) -> f64 {
+    let scale = material.density * material.Z_power_over_A(4.5) / (WATER.density * WATER.Z_power_over_A(4.5));
+    4.94 * (energy / 0.01).powf(-3.2) * 0.1 * scale
+}
+
+// Klein-Nishina total Compton cross section per electron (mm^2)
+pub fn klein_nishina(energy: f64) -> f64 {
+    let k = energy / Me;
+    let l = f64::ln(1.0 + 2.0*k);
+    2.0 * std::f64::consts::PI * Re * Re * (
+        (1.0 + k) / (k*k) * (2.0*(1.0 + k) / (1.0 + 2.0*k) - l/k)
+        + l / (2.0*k)
+        - (1.0 + 3.0*k) / (1.0 + 2.0*k).powf(2.0)
+    )
+}
+
+// Compton attenuation coefficient (1/mm)
+pub fn compton_mu(energy: f64, material: &Material) -> f64 {
+    material.electron_density() * klein_nishina(energy)
+}
+
+// Pair production attenuation coefficient (1/mm), interpolated from NIST XCOM for water (nuclear + electron field),
+// and scaled to other materials with the radiation length
+pub fn pair_mu(energy: f64, material: &Material) -> f64 {
+    let es = [1.5, 2.0, 3.0, 4.0, 5.0, 6.0, 8.0, 10.0, 15.0, 20.0, 30.0, 50.0, 100.0, 1000.0];
+    let mus = [9.778e-05, 3.898e-04, 1.123e-03, 1.925e-03, 2.678e-03, 3.369e-03, 4.576e-03, 5.597e-03, 7.553e-03, 8.998e-03, 1.100e-02, 1.333e-02, 1.589e-02, 1.945e-02];
+    let threshold = 2.0 * Me;
+    let value = match energy {
+        e if e <= threshold => 0.0,
+        e if e < es[0]      => mus[0] * (e - threshold) / (es[0] - threshold),  // linear down to threshold
+        e                   => log_log_interpolate(e, &es, &mus),
+    };
+    value * 0.1 * WATER.radiation_length() / material.radiation_length()
+}
+
+// Get total attenuation coefficient of a photon (1/mm)
+pub fn attenuation(particle: &Particle, material: &Material) -> f64 {
+    let e = energy(particle);
+    photoelectric_mu(e, material) + compton_mu(e, material) + pair_mu(e, material)
+}
+
+// Bremsstrahlung photon spectrum in the complete screening approximation: dN/dy = (4/3 - 4/3*y + y^2) / y per
+// radiation length, where y = k/E is the fraction of the electron energy carried by the photon
+
+// Number of photons emitted with a y between a and b per radiation length
+pub fn brems_photon_number(a: f64, b: f64) -> f64 {
+    4.0/3.0 * f64::ln(b/a) - 4.0/3.0 * (b - a) + 0.5 * (b*b - a*a)
+}
+
+// Fraction of the electron energy radiated as photons with a y between a and b per radiation length
+pub fn brems_energy_fraction(a: f64, b: f64) -> f64 {
+    4.0/3.0 * (b - a) - 2.0/3.0 * (b*b - a*a) + (b.powf(3.0) - a.powf(3.0)) / 3.0
+}
+
+// Sample the energy fraction y of a bremsstrahlung photon between y_cut and y_max
+pub fn sample_brems(rng: &mut impl Rng, y_cut: f64, y_max: f64) -> f64 {
+    loop {
+        // Sample from 1/y, then reject with the rest of the spectrum (at most 4/3)
+        let y = y_cut * (y_max / y_cut).powf(rng.random::<f64>());
+        if 4.0/3.0 * rng.random::<f64>() <= 4.0/3.0 - 4.0/3.0*y + y*y {
+            return y;
+        }
+    }
+}
+
+// Heitler cross section per electron (mm^2) for the two-photon annihilation in flight of a positron
+pub fn heitler(gamma: f64) -> f64 {
+    let g2m1 = gamma*gamma - 1.0;
+    std::f64::consts::PI * Re * Re / (gamma + 1.0) * (
+        (gamma*gamma + 4.0*gamma + 1.0) / g2m1 * f64::ln(gamma + g2m1.sqrt())
+        - (gamma + 3.0) / g2m1.sqrt()
+    )
+}
+
+// Annihilation in flight attenuation coefficient (1/mm)
+pub fn annihilation_mu(particle: &Particle, material: &Material) -> f64 {
+    material.electron_density() * heitler(gamma(particle).expect("Positron has no gamma factor."))
+}
+
+// Sample the two-photon annihilation in flight of a positron of kinetic energy ke (as in GEANT4)
+// Returns the fraction of the total energy carried by the first photon and the cosine of its angle
+pub fn sample_annihilation(rng: &mut impl Rng, ke: f64) -> (f64, f64) {
+    let tau = ke / Me;
+    let gam = tau + 1.0;
+    let tau2 = tau + 2.0;
+    let sqgrate = (tau / tau2).sqrt() * 0.5;
+    let sqg2m1 = (tau * tau2).sqrt();
+    let eps_min = 0.5 - sqgrate;
+    let eps_max = 0.5 + sqgrate;
+
+    loop {
+        let eps = eps_min * (eps_max / eps_min).powf(rng.random::<f64>());
+        let greject = 1.0 - eps + (2.0*gam*eps - 1.0) / (eps * tau2 * tau2);
+        if greject > rng.random::<f64>() {
+            let cos_theta = ((eps*tau2 - 1.0) / (eps*sqg2m1)).clamp(-1.0, 1.0);
+            return (eps, cos_theta);
+        }
+    }
+}
+
+// Sample the energy of the electron from a muon decay at rest from the Michel spectrum dN/dx = x^2 (3 - 2x),
+// where x is the electron energy in units of its maximum energy (m_mu^2 + m_e^2) / 2m_mu
+pub fn sample_michel(rng: &mut impl Rng) -> f64 {
+    let e_max = (Mmu*Mmu + Me*Me) / (2.0 * Mmu);
+    loop {
+        let x = rng.random::<f64>();
+        if x * e_max > Me && rng.random::<f64>() <= x*x * (3.0 - 2.0*x) {
+            return x * e_max;
+        }
+    }
+}
+
+// Sample the kinetic energy (MeV) of a neutron evaporated by an excited nucleus of temperature T (MeV),
+// from the Maxwellian evaporation spectrum dN/dE = E exp(-E/T)
+pub fn sample_evaporation(rng: &mut impl Rng, T: f64) -> f64 {
+    -T * f64::ln((1.0 - rng.random::<f64>()) * (1.0 - rng.random::<f64>()))
+}
+
+// Frank-Tamm Cherenkov photon yield (photons/mm) between LAMBDA_MIN and LAMBDA_MAX of a particle of unit charge
+// with velocity beta in a medium of refractive index n
+pub fn cherenkov_yield(beta: f64, n: f64) -> f64 {
+    if beta * n <= 1.0 {
+        return 0.0;
+    }
+    2.0 * std::f64::consts::PI * ALPHA * (1.0/LAMBDA_MIN - 1.0/LAMBDA_MAX) * 1e6 * (1.0 - 1.0/(beta*beta*n*n))
+}
+
+// Sample the wavelength (nm) of a Cherenkov photon from the 1/lambda^2 spectrum
+pub fn sample_cherenkov_wavelength(rng: &mut impl Rng) -> f64 {
+    1.0 / (1.0/LAMBDA_MAX + rng.random::<f64>() * (1.0/LAMBDA_MIN - 1.0/LAMBDA_MAX))
+}
+
+// Absorption length (mm) for a wavelength (nm): interpolated from Pope & Fry (1997) for pure water, and
+// Quickenden & Irvin (1980) in the UV, and scaled by the transparency of the material
+pub fn absorption_length(lambda: f64, material: &Material) -> f64 {
+    let ls = [300.0, 340.0, 380.0, 420.0, 460.0, 500.0, 550.0, 600.0];
+    let lengths = [2.2e4, 5.0e4, 8.8e4, 2.2e5, 1.1e5, 3.9e4, 1.77e4, 4.5e3];
+    log_log_interpolate(lambda, &ls, &lengths) * material.transparency
+}
+
+// Rayleigh scattering length (mm) for a wavelength (nm): scaling as lambda^4 from 100m at 400nm for water, and
+// scaled by the transparency of the material
+pub fn rayleigh_length(lambda: f64, material: &Material) -> f64 {
+    1.0e5 * (lambda / 400.0).powi(4) * material.transparency
+}
+
+// Sample the cosine of the Rayleigh scattering angle of an unpolarised photon, from (1 + cos^2)
+pub fn sample_rayleigh(rng: &mut impl Rng) -> f64 {
+    loop {
+        let cos_theta = 2.0 * rng.random::<f64>() - 1.0;
+        if 2.0 * rng.random::<f64>() < 1.0 + cos_theta*cos_theta {
+            return cos_theta;
+        }
+    }
+}
+
+// Fresnel reflection probability of an unpolarised photon going from index n1 to n2, for a cosine of the
+// incidence angle cos_i (total internal reflection beyond the critical angle)
+pub fn fresnel_reflectance(cos_i: f64, n1: f64, n2: f64) -> f64 {
+    let sin_t = n1 / n2 * (1.0 - cos_i*cos_i).sqrt();
+    if sin_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin_t*sin_t).sqrt();
+    let rs = ((n1*cos_i - n2*cos_t) / (n1*cos_i + n2*cos_t)).powi(2);
+    let rp = ((n1*cos_t - n2*cos_i) / (n1*cos_t + n2*cos_i)).powi(2);
+    (rs + rp) / 2.0
+}
+
+// Sample a Compton scattering from the Klein-Nishina distribution (Butcher & Messel method, as in GEANT4)
+// Returns the fraction of the photon energy kept (epsilon) and the cosine of the scattering angle
+pub fn sample_compton(rng: &mut impl Rng, energy: f64) -> (f64, f64) {
+    let k = energy / Me;
+    let eps0 = 1.0 / (1.0 + 2.0*k);
+    let eps0sq = eps0 * eps0;
+    let alpha1 = -f64::ln(eps0);
+    let alpha2 = alpha1 + 0.5 * (1.0 - eps0sq);
+
+    loop {
+        let (eps, epssq) = if alpha1 > alpha2 * rng.random::<f64>() {
+            let eps = f64::exp(-alpha1 * rng.random::<f64>());
+            (eps, eps * eps)
+        } else {
+            let epssq = eps0sq + (1.0 - eps0sq) * rng.random::<f64>();
+            (epssq.sqrt(), epssq)
+        };
+        let onecost = (1.0 - eps) / (eps * k);
+        let sint2 = onecost * (2.0 - onecost);
+        let greject = 1.0 - eps * sint2 / (1.0 + epssq);
+        if greject >= rng.random::<f64>() {
+            return (eps, 1.0 - onecost);
+        }
+    }
+}
+
 // Tests
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::geometry::material::{AIR, IRON, LAB, LEAD};
+    use crate::utils::constants::Mp;
+    use crate::utils::stopping::StoppingTable;
+    use crate::utils::stopping::tests::{ESTAR_WATER, PSTAR_WATER};
     use crate::utils::vec3::Vec3;
     use approx::assert_relative_eq;
+    use rand::{rngs::StdRng, SeedableRng};
 
     fn assert_gamma_eq(lhs: Option<f64>, rhs: Option<f64>) {
         match (lhs, rhs) {
@@ -97,9 +474,9 @@ mod tests {
         let p1 = Particle::new(Vec3(0.1, -1.5, -5.5), Vec3(-4.0, 0.0, 3.0), ParticleType::Electron);
         let p2 = Particle::new(Vec3(0.7, 9.8, 1.3), Vec3(3.0, 4.0, 0.0), ParticleType::Muon);
         let p3 = Particle::new(Vec3(1.5, -2.1, -4.8), Vec3(3.0, 4.0, 0.0), ParticleType::Gamma);
-        assert_relative_eq!(beta(&p1), 0.9948181376436321);
-        assert_relative_eq!(beta(&p2), 0.04726870197708133);
-        assert_relative_eq!(beta(&p3), 1.0);
+        assert_relative_eq!(beta(&p1, &WATER), 0.9948181376436321);
+        assert_relative_eq!(beta(&p2, &WATER), 0.04726870197708133);
+        assert_relative_eq!(beta(&p3, &WATER), 1.0);
     }
 
     #[test]
@@ -110,12 +487,186 @@ mod tests {
         let p4 = Particle::new(Vec3(0.0, 3.0, 5.0), Vec3(5.6, -2.1, -1.3), ParticleType::Muon);
         let p5 = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(9.7, 15.2, 51.1), ParticleType::Electron);
         let p6 = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(53.4, -98.3, -89.5), ParticleType::Muon);
-        assert_relative_eq!(dEdx(&p1), 0.8);
-        assert_relative_eq!(dEdx(&p2), 0.8);
-        assert_relative_eq!(dEdx(&p3), 0.18667002945559819);
-        assert_relative_eq!(dEdx(&p4), 0.8);
-        assert_relative_eq!(dEdx(&p5), 0.21359254760465154);
-        assert_relative_eq!(dEdx(&p6), 0.24890235819417583);
+        assert_relative_eq!(dEdx(&p1, &WATER), 0.8);
+        assert_relative_eq!(dEdx(&p2, &WATER), bethe_bloch(0.084 * Mmu, Mmu, 1.0, &WATER));
+        assert_relative_eq!(dEdx(&p3, &WATER), 0.18667002945559819);
+        assert_relative_eq!(dEdx(&p4, &WATER), bethe_bloch(0.084 * Mmu, Mmu, 1.0, &WATER));
+        assert_relative_eq!(dEdx(&p5, &WATER), 0.21359254760465154);
+        assert_relative_eq!(dEdx(&p6, &WATER), 0.24890235819417583);
+        // Continuity of the muon energy loss at the low momentum cutoff
+        let below = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 8.89), ParticleType::Muon);
+        let above = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 8.91), ParticleType::Muon);
+        assert_relative_eq!(dEdx(&below, &WATER), dEdx(&above, &WATER), max_relative = 0.01);
+    }
+
+    #[test]
+    fn test_physics_dEdx_tables() {
+        let mut water = WATER.clone();
+        water.add_stopping_table(StoppingTable::parse(ESTAR_WATER).unwrap());
+        water.add_stopping_table(StoppingTable::parse(PSTAR_WATER).unwrap());
+        // Electron with 1 MeV kinetic energy, proton with 100 MeV and muon at the same velocity
+        let electron = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, f64::sqrt(1.0 + 2.0 * Me)), ParticleType::Electron);
+        let proton = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, f64::sqrt(100.0*100.0 + 2.0 * 100.0 * Mp)), ParticleType::Proton);
+        let muon = Particle::new(Vec3(0.0, 0.0, 0.0), proton.state.p * (Mmu / Mp), ParticleType::Muon);
+        assert_relative_eq!(dEdx(&electron, &water), 0.1849, max_relative = 1e-9);
+        assert_relative_eq!(dEdx(&proton, &water), 0.7286, max_relative = 1e-9);
+        assert_relative_eq!(dEdx(&muon, &water), 0.7286, max_relative = 1e-9);
+        // The fits agree with the tables
+        assert_relative_eq!(dEdx(&electron, &WATER), dEdx(&electron, &water), max_relative = 0.03);
+        assert_relative_eq!(dEdx(&proton, &WATER), dEdx(&proton, &water), max_relative = 0.03);
+        // Photons are not affected
+        let gamma = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 1.0), ParticleType::Gamma);
+        assert_relative_eq!(attenuation(&gamma, &water), attenuation(&gamma, &WATER));
+    }
+
+    #[test]
+    fn test_physics_bethe_bloch() {
+        // Muons in water, compared to the PDG tables (without density effect)
+        assert_relative_eq!(bethe_bloch(20.0, Mmu, 1.0, &WATER), 3.03, max_relative = 0.02);
+        assert_relative_eq!(bethe_bloch(350.0, Mmu, 1.0, &WATER), 0.205, max_relative = 0.05);
+        // Continuity with the high momentum fit
+        let muon = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 50.0, 0.0), ParticleType::Muon);
+        assert_relative_eq!(bethe_bloch(49.999, Mmu, 1.0, &WATER), dEdx(&muon, &WATER), max_relative = 0.05);
+        // Scaling with the charge and the density
+        let material = Material { density: 2.0, ..WATER };
+        assert_relative_eq!(bethe_bloch(20.0, Mmu, 2.0, &WATER), 4.0 * bethe_bloch(20.0, Mmu, 1.0, &WATER));
+        assert_relative_eq!(bethe_bloch(20.0, Mmu, 1.0, &material), 2.0 * bethe_bloch(20.0, Mmu, 1.0, &WATER));
+        // Same beta*gamma, same energy loss
+        assert_relative_eq!(bethe_bloch(20.0 * 938.272 / Mmu, 938.272, 1.0, &WATER), bethe_bloch(20.0, Mmu, 1.0, &WATER), max_relative = 0.01);
+    }
+
+    #[test]
+    fn test_physics_brems() {
+        let mut rng = StdRng::seed_from_u64(12);
+        // The whole spectrum radiates exactly one radiation length's worth of energy
+        assert_relative_eq!(brems_energy_fraction(0.0, 1.0), 1.0);
+        assert_relative_eq!(brems_energy_fraction(0.0, 0.3) + brems_energy_fraction(0.3, 1.0), 1.0);
+        assert_relative_eq!(brems_photon_number(0.1, 1.0), 4.0/3.0 * f64::ln(10.0) - 1.2 + 0.495);
+        for _ in 0..100 {
+            let y = sample_brems(&mut rng, 0.01, 0.9);
+            assert!((0.01..=0.9).contains(&y));
+        }
+    }
+
+    #[test]
+    fn test_physics_annihilation() {
+        let mut rng = StdRng::seed_from_u64(5);
+        // Cross section falls with energy
+        assert!(heitler(1.5) > heitler(3.0) && heitler(3.0) > heitler(30.0));
+        let positron = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(1.0, 0.0, 0.0), ParticleType::Positron);
+        assert_relative_eq!(annihilation_mu(&positron, &WATER), WATER.electron_density() * heitler(gamma(&positron).unwrap()));
+        for ke in [0.1, 1.0, 10.0] {
+            let (eps, cos_theta) = sample_annihilation(&mut rng, ke);
+            assert!(0.0 < eps && eps < 1.0);
+            assert!((-1.0..=1.0).contains(&cos_theta));
+        }
+    }
+
+    #[test]
+    fn test_physics_sample_michel() {
+        let mut rng = StdRng::seed_from_u64(9);
+        let es = (0..10000).map(|_| sample_michel(&mut rng)).collect::<Vec<f64>>();
+        assert!(es.iter().all(|&e| Me < e && e <= 52.83));
+        // Mean of x^2 (3 - 2x) is 0.7
+        assert_relative_eq!(es.iter().sum::<f64>() / es.len() as f64, 0.7 * 52.83, max_relative = 0.01);
+    }
+
+    #[test]
+    fn test_physics_sample_evaporation() {
+        let mut rng = StdRng::seed_from_u64(10);
+        let es = (0..10000).map(|_| sample_evaporation(&mut rng, 1.5)).collect::<Vec<f64>>();
+        assert!(es.iter().all(|&e| e > 0.0));
+        // Mean of E exp(-E/T) is 2T
+        assert_relative_eq!(es.iter().sum::<f64>() / es.len() as f64, 3.0, max_relative = 0.03);
+    }
+
+    #[test]
+    fn test_physics_energy_loss() {
+        let mut rng = StdRng::seed_from_u64(12);
+        let moments = |losses: &[f64]| {
+            let mean = losses.iter().sum::<f64>() / losses.len() as f64;
+            (mean, (losses.iter().map(|l| (l - mean).powi(2)).sum::<f64>() / losses.len() as f64).sqrt())
+        };
+
+        // Landau: median of 1.356
+        let mut lambdas = (0..100000).map(|_| sample_landau(&mut rng)).collect::<Vec<f64>>();
+        lambdas.sort_by(f64::total_cmp);
+        assert_relative_eq!(lambdas[50000], 1.356, epsilon = 0.02);
+
+        // Thin step of a fast muon: skewed Landau fluctuations (most losses below the mean), which keep the mean loss
+        let muon = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 1000.0), ParticleType::Muon);
+        let mean = dEdx(&muon, &WATER) * 10.0;
+        let losses = (0..100000).map(|_| sample_energy_loss(&mut rng, &muon, &WATER, mean, 10.0)).collect::<Vec<f64>>();
+        assert!(losses.iter().all(|&l| l >= 0.0));
+        assert_relative_eq!(moments(&losses).0, mean, max_relative = 0.01);
+        assert!(losses.iter().filter(|&&l| l < mean).count() > 60000);
+
+        // Intermediate step of a proton: Urban model
+        let proton = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 300.0), ParticleType::Proton);
+        let mean = dEdx(&proton, &WATER) * 2.0;
+        let losses = (0..20000).map(|_| sample_energy_loss(&mut rng, &proton, &WATER, mean, 2.0)).collect::<Vec<f64>>();
+        let (average, sigma) = moments(&losses);
+        assert_relative_eq!(average, mean, max_relative = 0.01);
+        assert!(sigma > 0.01 * mean && sigma < 0.2 * mean);
+
+        // Thick step of a slow proton: Gaussian with the Bohr width sqrt(xi T_max (1 - beta^2 / 2))
+        let proton = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 100.0), ParticleType::Proton);
+        let mean = dEdx(&proton, &WATER) * 0.5;
+        let beta2 = beta(&proton, &WATER).powi(2);
+        let xi = 0.5 * K * WATER.Z_over_A() * WATER.density * 0.05 / beta2;
+        let bohr = (xi * max_energy_transfer(100.0, Mp) * (1.0 - beta2 / 2.0)).sqrt();
+        let losses = (0..20000).map(|_| sample_energy_loss(&mut rng, &proton, &WATER, mean, 0.5)).collect::<Vec<f64>>();
+        let (average, sigma) = moments(&losses);
+        assert_relative_eq!(average, mean, max_relative = 0.01);
+        assert_relative_eq!(sigma, bohr, max_relative = 0.03);
+    }
+
+    #[test]
+    fn test_physics_dEdx_hadrons() {
+        let pion = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(100.0, 0.0, 0.0), ParticleType::PionPlus);
+        let proton1 = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 500.0, 0.0), ParticleType::Proton);
+        let proton2 = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 50.0, 0.0), ParticleType::Proton);
+        let proton3 = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 10.0, 0.0), ParticleType::Proton);
+        assert_relative_eq!(dEdx(&pion, &WATER), bethe_bloch(100.0, 139.570, 1.0, &WATER));
+        // PSTAR: ~6.2 MeV cm2/g for a 125 MeV proton
+        assert_relative_eq!(dEdx(&proton1, &WATER), 0.62, max_relative = 0.03);
+        assert_relative_eq!(dEdx(&proton2, &WATER), dEdx(&proton3, &WATER));
+    }
+
+    #[test]
+    fn test_physics_cherenkov() {
+        let mut rng = StdRng::seed_from_u64(13);
+        // Below threshold
+        assert_relative_eq!(cherenkov_yield(0.7, 1.33), 0.0);
+        assert_relative_eq!(cherenkov_yield(1.0 / 1.33, 1.33), 0.0);
+        // ~33 photons/mm between 300nm and 600nm for beta = 1 in water
+        assert_relative_eq!(cherenkov_yield(1.0, 1.33), 33.2, max_relative = 0.01);
+        assert!(cherenkov_yield(0.8, 1.33) < cherenkov_yield(0.9, 1.33));
+        let ls = (0..1000).map(|_| sample_cherenkov_wavelength(&mut rng)).collect::<Vec<f64>>();
+        assert!(ls.iter().all(|l| (LAMBDA_MIN..=LAMBDA_MAX).contains(l)));
+        // Blue photons are more likely
+        assert!(ls.iter().filter(|&&l| l < 400.0).count() > ls.iter().filter(|&&l| l > 500.0).count());
+    }
+
+    #[test]
+    fn test_physics_optical() {
+        let mut rng = StdRng::seed_from_u64(19);
+        // Water is most transparent in the blue
+        assert_relative_eq!(absorption_length(420.0, &WATER), 2.2e5, max_relative = 1e-9);
+        assert!(absorption_length(400.0, &WATER) > absorption_length(550.0, &WATER));
+        assert_relative_eq!(absorption_length(250.0, &WATER), 2.2e4);
+        assert_relative_eq!(rayleigh_length(400.0, &WATER), 1.0e5);
+        assert_relative_eq!(rayleigh_length(800.0, &WATER), 1.6e6);
+        // Rayleigh scattering is forward-backward symmetric, with <cos^2> = 2/5
+        let cs = (0..10000).map(|_| sample_rayleigh(&mut rng)).collect::<Vec<f64>>();
+        assert!(cs.iter().all(|c| (-1.0..=1.0).contains(c)));
+        assert_relative_eq!(cs.iter().sum::<f64>() / 10000.0, 0.0, epsilon = 0.03);
+        assert_relative_eq!(cs.iter().map(|c| c*c).sum::<f64>() / 10000.0, 0.4, max_relative = 0.03);
+        // Normal incidence, grazing incidence and total internal reflection
+        assert_relative_eq!(fresnel_reflectance(1.0, 1.33, 1.0), (0.33f64 / 2.33).powi(2), max_relative = 1e-9);
+        assert_relative_eq!(fresnel_reflectance(0.0, 1.0, 1.33), 1.0, max_relative = 1e-9);
+        assert_relative_eq!(fresnel_reflectance(0.5, 1.33, 1.0), 1.0);
+        assert!(fresnel_reflectance(0.8, 1.33, 1.0) < 0.1);
     }
 
     #[test]
@@ -133,4 +684,59 @@ mod tests {
         assert_relative_eq!(ke(&p5), 53.679415397928075);
         assert_relative_eq!(ke(&p6), 72.35330175017819);
     }
+
+    #[test]
+    fn test_physics_attenuation() {
+        // Compare to NIST XCOM values for water (without coherent scattering)
+        let p1 = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.02, 0.0, 0.0), ParticleType::Gamma);
+        let p2 = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 1.0, 0.0), ParticleType::Gamma);
+        let p3 = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 10.0), ParticleType::Gamma);
+        assert_relative_eq!(attenuation(&p1, &WATER), 0.0719, max_relative = 0.05);
+        assert_relative_eq!(attenuation(&p2, &WATER), 0.00707, max_relative = 0.02);
+        assert_relative_eq!(attenuation(&p3, &WATER), 0.00222, max_relative = 0.02);
+        assert_relative_eq!(pair_mu(1.0, &WATER), 0.0);
+        assert!(pair_mu(1.2, &WATER) > 0.0 && pair_mu(1.2, &WATER) < pair_mu(1.5, &WATER));
+        assert!(photoelectric_mu(0.02, &WATER) > compton_mu(0.02, &WATER));
+        assert!(photoelectric_mu(0.1, &WATER) < compton_mu(0.1, &WATER));
+    }
+
+    #[test]
+    fn test_physics_materials() {
+        // Compare to the PDG minimum ionisation and NIST XCOM values for iron and lead
+        let muon = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 1000.0), ParticleType::Muon);
+        let gamma = Particle::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 1.0), ParticleType::Gamma);
+        assert_relative_eq!(dEdx(&muon, &IRON), 1.143, max_relative = 0.1);
+        assert_relative_eq!(dEdx(&muon, &LEAD), 1.273, max_relative = 0.1);
+        assert_relative_eq!(attenuation(&gamma, &IRON), 0.0472, max_relative = 0.03);
+        assert_relative_eq!(attenuation(&gamma, &LEAD), 0.0793, max_relative = 0.25);
+        // Lighter materials stop less and are more transparent
+        assert!(dEdx(&muon, &LAB) < dEdx(&muon, &WATER) && dEdx(&muon, &AIR) < 1e-3);
+        assert!(attenuation(&gamma, &LAB) < attenuation(&gamma, &WATER));
+        // Photoelectric absorption dominates at low energy in heavy materials
+        assert!(photoelectric_mu(0.1, &LEAD) > compton_mu(0.1, &LEAD));
+        assert!(pair_mu(10.0, &LEAD) > 20.0 * pair_mu(10.0, &WATER));
+        // Opaque materials absorb optical photons
+        assert_relative_eq!(absorption_length(400.0, &LAB), 0.1 * absorption_length(400.0, &WATER));
+        assert_relative_eq!(absorption_length(400.0, &LEAD), 0.0);
+        // Bound muons live shorter and K-shells are bound tighter in heavier atoms, interpolating between the tables
+        assert_relative_eq!(bound_muon_lifetime(8.0), 1795.4, max_relative = 1e-9);
+        assert_relative_eq!(bound_muon_lifetime(1.0), 2194.9, max_relative = 1e-9);
+        assert!(bound_muon_lifetime(26.0) < bound_muon_lifetime(18.0) && bound_muon_lifetime(18.0) < bound_muon_lifetime(14.0));
+        assert_relative_eq!(k_shell_binding(8.0), 543.1e-6, max_relative = 1e-9);
+        assert!(k_shell_binding(4.0) > k_shell_binding(1.0) && k_shell_binding(4.0) < k_shell_binding(6.0));
+    }
+
+    #[test]
+    fn test_physics_sample_compton() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for e in [0.05, 0.5, 5.0] {
+            let eps_min = 1.0 / (1.0 + 2.0 * e / Me);
+            for _ in 0..100 {
+                let (eps, cos_theta) = sample_compton(&mut rng, e);
+                assert!(eps_min <= eps && eps <= 1.0);
+                // Compton formula
+                assert_relative_eq!(1.0/(e*eps) - 1.0/e, (1.0 - cos_theta) / Me, max_relative = 1e-9);
+            }
+        }
+    }
 }
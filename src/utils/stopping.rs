@@ -0,0 +1,148 @@
+use crate::utils::constants::{Malpha, Me, Mp};
+use crate::utils::operations::log_log_interpolate;
+
+// Projectile of a NIST stopping power table
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projectile {
+    Electron,  // ESTAR
+    Proton,    // PSTAR
+    Alpha,     // ASTAR
+}
+
+impl Projectile {
+    // Mass (MeV) and charge of the projectile
+    pub fn mass_charge(self) -> (f64, f64) {
+        match self {
+            Projectile::Electron => (Me, 1.0),
+            Projectile::Proton   => (Mp, 1.0),
+            Projectile::Alpha    => (Malpha, 2.0),
+        }
+    }
+}
+
+// Stopping power table, as produced by the NIST ESTAR, PSTAR and ASTAR programs
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoppingTable {
+    pub projectile: Projectile,
+    pub energies: Vec<f64>,  // kinetic energies (MeV)
+    pub stopping: Vec<f64>,  // collision (ESTAR) or electronic (PSTAR, ASTAR) stopping powers (MeV cm^2/g)
+}
+
+impl StoppingTable {
+    // Parse the plain text output of ESTAR, PSTAR or ASTAR. The projectile is read from the header, and the first
+    // two columns of the data lines are the kinetic energy and the collision/electronic stopping power.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let projectile = if text.contains("ESTAR") {
+            Projectile::Electron
+        } else if text.contains("PSTAR") {
+            Projectile::Proton
+        } else if text.contains("ASTAR") {
+            Projectile::Alpha
+        } else {
+            return Err("not an ESTAR, PSTAR or ASTAR table".to_string());
+        };
+
+        let (mut energies, mut stopping) = (vec![], vec![]);
+        for line in text.lines() {
+            // Header lines contain words, data lines only numbers
+            let Ok(values) = line.split_whitespace().map(str::parse::<f64>).collect::<Result<Vec<f64>, _>>() else {
+                continue;
+            };
+            if values.len() < 2 {
+                continue;
+            }
+            if values[0] <= energies.last().copied().unwrap_or(0.0) || values[1] <= 0.0 {
+                return Err(format!("invalid stopping power table line: {}", line.trim()));
+            }
+            energies.push(values[0]);
+            stopping.push(values[1]);
+        }
+        if energies.len() < 2 {
+            return Err("stopping power table has fewer than two lines".to_string());
+        }
+        Ok(StoppingTable { projectile, energies, stopping })
+    }
+
+    // Mass stopping power (MeV cm^2/g) of a particle with mass m (MeV), charge z and kinetic energy ke (MeV),
+    // interpolated in log-log space. Heavy particles are looked up at the same velocity as the tabulated projectile,
+    // and scaled by the square of their charge.
+    pub fn mass_stopping(&self, ke: f64, m: f64, z: f64) -> f64 {
+        match self.projectile {
+            Projectile::Electron => log_log_interpolate(ke, &self.energies, &self.stopping),
+            _ => {
+                let (mass, charge) = self.projectile.mass_charge();
+                (z / charge).powi(2) * log_log_interpolate(ke * mass / m, &self.energies, &self.stopping)
+            }
+        }
+    }
+}
+
+
+// Tests
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    // Excerpts of the NIST tables for liquid water
+    pub const ESTAR_WATER: &str = "
+ ESTAR: Stopping Powers and Range Tables for Electrons
+
+ WATER, LIQUID
+
+ Kinetic   Collision  Radiative  Total      CSDA       Radiation  Density
+ Energy    Stp. Pow.  Stp. Pow.  Stp. Pow.  Range      Yield      Effect
+ MeV       MeV cm2/g  MeV cm2/g  MeV cm2/g  g/cm2                 Parameter
+
+ 1.000E-02 2.256E+01 3.898E-03 2.256E+01 2.515E-04 9.408E-05 0.000E+00
+ 1.000E-01 4.115E+00 5.862E-03 4.121E+00 1.431E-02 6.311E-04 0.000E+00
+ 1.000E+00 1.849E+00 1.280E-02 1.862E+00 4.367E-01 3.669E-03 2.428E-01
+ 1.000E+01 1.968E+00 1.832E-01 2.151E+00 4.975E+00 4.163E-02 1.713E+00
+ 1.000E+02 2.197E+00 2.399E+00 4.596E+00 3.255E+01 3.165E-01 5.837E+00
+";
+
+    pub const PSTAR_WATER: &str = "
+ PSTAR: Stopping Powers and Range Tables for Protons
+
+ WATER, LIQUID
+
+ Kinetic   Electr.   Nuclear   Total     CSDA      Projected  Detour
+ Energy    Stp. Pow. Stp. Pow. Stp. Pow. Range     Range      Factor
+ MeV       MeV cm2/g MeV cm2/g MeV cm2/g g/cm2     g/cm2
+
+ 1.000E+00 2.606E+02 1.766E-01 2.608E+02 2.458E-03 2.417E-03 9.833E-01
+ 1.000E+01 4.564E+01 3.068E-02 4.567E+01 1.230E-01 1.226E-01 9.968E-01
+ 1.000E+02 7.286E+00 3.752E-03 7.289E+00 7.718E+00 7.703E+00 9.981E-01
+ 1.000E+03 2.210E+00 4.138E-04 2.211E+00 3.247E+02 3.227E+02 9.938E-01
+";
+
+    #[test]
+    fn test_stopping_parse() {
+        let table = StoppingTable::parse(ESTAR_WATER).unwrap();
+        assert_eq!(table.projectile, Projectile::Electron);
+        assert_eq!(table.energies, vec![0.01, 0.1, 1.0, 10.0, 100.0]);
+        assert_eq!(table.stopping[2], 1.849);
+        assert_eq!(StoppingTable::parse(PSTAR_WATER).unwrap().projectile, Projectile::Proton);
+        assert_eq!(StoppingTable::parse(&PSTAR_WATER.replace("PSTAR", "ASTAR")).unwrap().projectile, Projectile::Alpha);
+
+        assert!(StoppingTable::parse("1.0 2.0\n3.0 4.0").is_err());
+        assert!(StoppingTable::parse("ESTAR\n1.0 2.0").is_err());
+        assert!(StoppingTable::parse("ESTAR\n1.0 2.0\n0.5 3.0").is_err());
+    }
+
+    #[test]
+    fn test_stopping_interpolation() {
+        let estar = StoppingTable::parse(ESTAR_WATER).unwrap();
+        assert_relative_eq!(estar.mass_stopping(1.0, Me, 1.0), 1.849);
+        assert_relative_eq!(estar.mass_stopping(f64::sqrt(10.0), Me, 1.0), f64::sqrt(1.849 * 1.968));
+        // Clamped outside the table
+        assert_relative_eq!(estar.mass_stopping(1000.0, Me, 1.0), 2.197);
+
+        // Heavy particles at the same velocity as the proton
+        let pstar = StoppingTable::parse(PSTAR_WATER).unwrap();
+        assert_relative_eq!(pstar.mass_stopping(10.0, Mp, 1.0), 45.64);
+        assert_relative_eq!(pstar.mass_stopping(10.0 * Malpha / Mp, Malpha, 2.0), 4.0 * 45.64);
+        let astar = StoppingTable::parse(&PSTAR_WATER.replace("PSTAR", "ASTAR")).unwrap();
+        assert_relative_eq!(astar.mass_stopping(10.0 * Mp / Malpha, Mp, 1.0), 45.64 / 4.0);
+    }
+}
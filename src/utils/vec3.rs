@@ -4,9 +4,10 @@ use rand_distr::{Normal, Distribution};
 use std::ops::{Add, AddAssign, Sub, Mul, Div, Neg};
 use std::cmp::{PartialEq};
 use approx::relative_eq;
+use serde::Serialize;
 use crate::utils::operations::orthonormal_basis;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize)]
 pub struct Vec3(pub f64, pub f64, pub f64);
 
 impl Vec3 {
@@ -46,6 +47,13 @@ impl Vec3 {
 
         *self = (*self + thetax * u + thetay * v).norm() * self.mag();
     }
+
+    // Rotate by polar angle theta (w.r.t. the current direction) and azimuthal angle phi
+    pub fn rotate(self, theta: f64, phi: f64) -> Vec3 {
+        let (u, v) = orthonormal_basis(self);
+        let dir = self.norm() * theta.cos() + (u * phi.cos() + v * phi.sin()) * theta.sin();
+        dir * self.mag()
+    }
 }
 
 // Addition (+)
@@ -285,4 +293,15 @@ mod tests {
         assert_vec3_eq!(v1.cross(v3), -v3.cross(v1));
         assert_vec3_eq!(v2.cross(v3), -v3.cross(v2));
     }
+
+    #[test]
+    fn test_vec3_rotate() {
+        let v1 = Vec3(0.0, 0.0, 2.0);
+        let v2 = Vec3(3.0, -4.0, 0.0);
+        assert_vec3_eq!(v1.rotate(0.0, 1.3), v1);
+        assert_relative_eq!(v1.rotate(std::f64::consts::PI, 0.4).2, -2.0);
+        assert_relative_eq!(v2.rotate(0.7, 2.1).mag(), v2.mag());
+        assert_relative_eq!(v2.rotate(0.7, 2.1).norm().dot(v2.norm()), f64::cos(0.7));
+        assert_relative_eq!(v2.rotate(1.9, -0.3).norm().dot(v2.norm()), f64::cos(1.9));
+    }
 }